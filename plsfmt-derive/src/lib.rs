@@ -0,0 +1,220 @@
+//! Derive macro for implementing plsfmt macro formatters from grammar structs
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Fields, LitStr, Path, Type, parse_macro_input};
+
+/// Derives `syn::parse::Parse` and the plsfmt printer for a struct with named fields
+/// that describes the grammar of a macro. The fields are parsed in order, and printed in order
+/// separated by a space.
+///
+/// With `#[macro_syntax(name = "my_macro")]` on the struct, `plsfmt::MacroFactory` is also
+/// implemented, such that the struct can be registered as the formatter of `my_macro!`. With
+/// `#[macro_syntax(name = "select", path = "tokio::select")]` the formatter is bound to the full
+/// path of the macro. The content of the macro is printed between the delimiters of the
+/// invocation, on indented lines between braces.
+///
+/// Field attributes:
+/// - `#[macro_syntax(repeat)]` on a `Vec` field: parse elements until the end of the input
+/// - `#[macro_syntax(one_per_line)]` on a `Vec` field: like `repeat`, printed one element per
+///   line
+/// - `#[macro_syntax(separated)]` on a `syn::punctuated::Punctuated` field: parse separated
+///   elements until the end of the input
+/// - `#[macro_syntax(parse_with = path)]`: parse the field, or each element with `repeat`,
+///   `one_per_line` or `separated`, with the given function, e.g.
+///   `syn::Pat::parse_multi_with_leading_vert`
+/// - `#[macro_syntax(break_before)]`: move the field to the next line if it does not fit
+/// - `#[macro_syntax(no_space)]`: no space before the field, e.g. for a trailing comma
+#[proc_macro_derive(MacroSyntax, attributes(macro_syntax))]
+pub fn derive_macro_syntax(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[derive(Default)]
+struct FieldAttrs {
+    repeat: bool,
+    one_per_line: bool,
+    separated: bool,
+    parse_with: Option<Path>,
+    break_before: bool,
+    no_space: bool,
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            input,
+            "MacroSyntax can only be derived for structs",
+        ));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(syn::Error::new_spanned(
+            input,
+            "MacroSyntax can only be derived for structs with named fields",
+        ));
+    };
+
+    let mut name = None;
+    let mut path = None;
+    for attr in &input.attrs {
+        if attr.path().is_ident("macro_syntax") {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("name") {
+                    name = Some(meta.value()?.parse::<LitStr>()?);
+                    Ok(())
+                } else if meta.path.is_ident("path") {
+                    path = Some(meta.value()?.parse::<LitStr>()?);
+                    Ok(())
+                } else {
+                    Err(meta.error("unsupported macro_syntax attribute"))
+                }
+            })?;
+        }
+    }
+
+    // Through the re-export, such that users do not need a direct syn dependency
+    let syn = quote!(::plsfmt::__private::syn);
+    let mut parse_fields = Vec::new();
+    let mut print_fields = Vec::new();
+    for (index, field) in fields.named.iter().enumerate() {
+        let attrs = field_attrs(field)?;
+        let ident = &field.ident;
+
+        let parse_element = match &attrs.parse_with {
+            Some(path) => quote!(#path(input)?),
+            None => quote!(input.parse()?),
+        };
+        let parse = if attrs.repeat || attrs.one_per_line {
+            quote!({
+                let mut elements = ::std::vec::Vec::new();
+                while !input.is_empty() {
+                    elements.push(#parse_element);
+                }
+                elements
+            })
+        } else if attrs.separated {
+            match &attrs.parse_with {
+                Some(path) => {
+                    quote!(#syn::punctuated::Punctuated::parse_terminated_with(input, #path)?)
+                }
+                None => quote!(#syn::punctuated::Punctuated::parse_terminated(input)?),
+            }
+        } else {
+            parse_element
+        };
+        parse_fields.push(quote!(#ident: #parse));
+
+        let space = index > 0 && !attrs.no_space;
+        let print_value = if attrs.one_per_line {
+            quote!(::plsfmt::__private::lines(printer, value))
+        } else if attrs.repeat {
+            quote!(::plsfmt::__private::repeated(printer, value))
+        } else if attrs.separated {
+            quote!(::plsfmt::__private::separated(printer, value))
+        } else if attrs.break_before {
+            quote!(::plsfmt::__private::break_before(printer, value, #space))
+        } else {
+            quote!(::plsfmt::__private::Print::print(value, printer))
+        };
+        let space = (space && !attrs.break_before).then(|| quote!(printer.word(" ");));
+        print_fields.push(if is_option(&field.ty) {
+            quote! {
+                if let ::std::option::Option::Some(value) = &self.#ident {
+                    #space
+                    #print_value;
+                }
+            }
+        } else {
+            quote! {
+                let value = &self.#ident;
+                #space
+                #print_value;
+            }
+        });
+    }
+
+    let ty = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let mut expanded = quote! {
+        impl #impl_generics #syn::parse::Parse for #ty #ty_generics #where_clause {
+            fn parse(input: #syn::parse::ParseStream) -> #syn::Result<Self> {
+                ::std::result::Result::Ok(Self {
+                    #(#parse_fields,)*
+                })
+            }
+        }
+
+        impl #impl_generics ::plsfmt::__private::Print for #ty #ty_generics #where_clause {
+            fn print(&self, printer: &mut ::plsfmt::__private::Printer) {
+                printer.ibox(0);
+                #(#print_fields)*
+                printer.end();
+            }
+        }
+    };
+
+    if let Some(name) = name {
+        let path = match path {
+            Some(path) => quote!(::std::option::Option::Some(#path)),
+            None => quote!(::std::option::Option::None),
+        };
+        expanded.extend(quote! {
+            impl #impl_generics ::plsfmt::MacroFactory for #ty #ty_generics #where_clause {
+                fn name() -> &'static str {
+                    #name
+                }
+
+                fn path() -> ::std::option::Option<&'static str> {
+                    #path
+                }
+
+                fn parse(
+                    mac: &#syn::Macro,
+                ) -> ::std::option::Option<::std::boxed::Box<dyn ::plsfmt::MacroSyntax>> {
+                    let syntax: Self = #syn::parse2(mac.tokens.clone()).ok()?;
+                    ::std::option::Option::Some(::std::boxed::Box::new(
+                        ::plsfmt::__private::Delimited::new(mac, syntax),
+                    ))
+                }
+            }
+        });
+    }
+
+    Ok(expanded)
+}
+
+fn field_attrs(field: &syn::Field) -> syn::Result<FieldAttrs> {
+    let mut attrs = FieldAttrs::default();
+    for attr in &field.attrs {
+        if !attr.path().is_ident("macro_syntax") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("repeat") {
+                attrs.repeat = true;
+            } else if meta.path.is_ident("one_per_line") {
+                attrs.one_per_line = true;
+            } else if meta.path.is_ident("separated") {
+                attrs.separated = true;
+            } else if meta.path.is_ident("parse_with") {
+                attrs.parse_with = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("break_before") {
+                attrs.break_before = true;
+            } else if meta.path.is_ident("no_space") {
+                attrs.no_space = true;
+            } else {
+                return Err(meta.error("unsupported macro_syntax attribute"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(attrs)
+}
+
+fn is_option(ty: &Type) -> bool {
+    matches!(ty, Type::Path(path) if path.qself.is_none()
+        && path.path.segments.last().is_some_and(|segment| segment.ident == "Option"))
+}
@@ -0,0 +1,114 @@
+use plsfmt::{Formatter, MacroSyntax};
+use syn::punctuated::Punctuated;
+use syn::{Expr, Pat, Token};
+
+#[derive(MacroSyntax)]
+#[macro_syntax(name = "derived_select")]
+struct DerivedSelect {
+    #[macro_syntax(one_per_line)]
+    arms: Vec<DerivedArm>,
+}
+
+#[derive(MacroSyntax)]
+struct DerivedArm {
+    #[macro_syntax(parse_with = Pat::parse_multi_with_leading_vert)]
+    pat: Pat,
+    eq: Token![=],
+    future: Expr,
+    fat_arrow: Token![=>],
+    #[macro_syntax(break_before)]
+    body: Expr,
+    #[macro_syntax(no_space)]
+    comma: Option<Token![,]>,
+}
+
+#[derive(MacroSyntax)]
+#[macro_syntax(name = "derived_patterns")]
+struct DerivedPatterns {
+    #[macro_syntax(separated, parse_with = Pat::parse_multi_with_leading_vert)]
+    patterns: Punctuated<Pat, Token![,]>,
+}
+
+#[test]
+fn test_derive_macro_syntax() {
+    let code = r#"
+fn func() {
+    derived_select! {
+        val1=future1.expr()=>a.expr(),
+        Some(val2)   =   future2.expr()  =>   { b.expr() }
+        val3 = future3.expr() => cccccccccccccccccccccccccccccc.expr(dddddddddddddddddddddddd)
+    }
+}
+        "#;
+
+    let formatter = Formatter::builder()
+        .macro_factory::<DerivedSelect>()
+        .build();
+    let formatted = formatter.format_str(code).unwrap();
+
+    let expected_formatted = r#"
+fn func() {
+    derived_select! {
+        val1 = future1.expr() => a.expr(),
+        Some(val2) = future2.expr() => { b.expr() }
+        val3 = future3.expr() =>
+            cccccccccccccccccccccccccccccc.expr(dddddddddddddddddddddddd)
+    }
+}
+        "#;
+
+    assert_eq!(formatted, expected_formatted);
+}
+
+#[test]
+fn test_derive_separated_parse_with() {
+    let code = r#"
+fn func() {
+    derived_patterns! { Some(a)|None ,  b }
+}
+        "#;
+
+    let formatter = Formatter::builder()
+        .macro_factory::<DerivedPatterns>()
+        .build();
+    let formatted = formatter.format_str(code).unwrap();
+
+    let expected_formatted = r#"
+fn func() {
+    derived_patterns! {
+        Some(a) | None, b
+    }
+}
+        "#;
+
+    assert_eq!(formatted, expected_formatted);
+}
+
+#[test]
+fn test_derive_delimiters() {
+    let code = r#"
+fn func() {
+    derived_patterns!(Some(a)|None ,  b);
+    let patterns = derived_patterns![Some(a)|None ,  b];
+    derived_patterns!(Some(aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa) | None, Some(bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb));
+}
+        "#;
+
+    let formatter = Formatter::builder()
+        .macro_factory::<DerivedPatterns>()
+        .build();
+    let formatted = formatter.format_str(code).unwrap();
+
+    let expected_formatted = r#"
+fn func() {
+    derived_patterns!(Some(a) | None, b);
+    let patterns = derived_patterns![Some(a) | None, b];
+    derived_patterns!(
+        Some(aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa) | None,
+        Some(bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb)
+    );
+}
+        "#;
+
+    assert_eq!(formatted, expected_formatted);
+}
@@ -0,0 +1,553 @@
+use crate::cli;
+use crate::cli::git::{self, FileStatus, IndexEntry};
+use crate::cli::journal::{self, Journal};
+use crate::macros::infer::InferredShapes;
+use crate::{ConfigFile, FormatReport, Formatter};
+use cargo_metadata::Package;
+use clap::{Parser, ValueEnum};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[clap(rename_all = "kebab-case")]
+enum EmitMode {
+    /// Emits to files.
+    Files,
+    /// Writes a JSON report of the formatted macros to stdout without modifying files.
+    Json,
+}
+
+#[derive(Parser)]
+#[command(styles = clap_cargo::style::CLAP_STYLING)]
+struct Opts {
+    #[arg(short = 'p', long = "package", value_name = "package")]
+    package: Option<String>,
+
+    #[arg(long = "emit", value_enum, default_value_t = EmitMode::Files, conflicts_with_all = ["staged", "undo"])]
+    emit_mode: EmitMode,
+
+    /// Format the staged version of each staged file and update the index. Unstaged
+    /// changes in the working tree are kept. Staged files outside the targets of the selected
+    /// packages are not formatted.
+    #[arg(long)]
+    staged: bool,
+
+    /// Format files even if they have uncommitted changes
+    #[arg(long)]
+    allow_dirty: bool,
+
+    /// Format files even if they have staged changes
+    #[arg(long)]
+    allow_staged: bool,
+
+    /// Format files even if they are not in a git repository
+    #[arg(long)]
+    allow_no_vcs: bool,
+
+    /// Write the original content of each formatted file to `<file>.plsfmt.bak`
+    #[arg(long)]
+    backup: bool,
+
+    /// Restore the files modified by the most recent run
+    #[arg(long, conflicts_with_all = ["staged", "backup"])]
+    undo: bool,
+
+    /// Only format files matching the glob pattern, relative to the package root, e.g.
+    /// `tests/**` or `src/net/*.rs`
+    #[arg(long = "include", value_name = "glob")]
+    include: Vec<String>,
+
+    /// Do not format files matching the glob pattern, relative to the package root. Also applies
+    /// to files passed directly, relative to the current directory for files outside the packages
+    #[arg(long = "exclude", value_name = "glob")]
+    exclude: Vec<String>,
+
+    /// Files to format, or patterns matched as substrings against the path of files relative
+    /// to the `src` directory of the package
+    #[arg(value_name = "pattern|file")]
+    patterns: Vec<String>,
+}
+
+/// Runs `cargo plsfmt` with the given formatter. Can be used to build a custom cargo
+/// subcommand with additional macro formatters registered, in which case the subcommand name
+/// is derived from the binary name `cargo-<subcommand>`. Macros configured in `plsfmt.toml` in
+/// the workspace root and macros with shapes inferred from `macro_rules!` definitions in the
+/// packages are registered in addition.
+pub fn run(mut formatter: Formatter) {
+    let mut args = env::args().peekable();
+    let subcommand = args
+        .peek()
+        .and_then(|bin| Path::new(bin).file_stem()?.to_str()?.strip_prefix("cargo-"))
+        .unwrap_or("plsfmt")
+        .to_string();
+
+    let mut found_fmt = false;
+    let args = args.filter(|x| {
+        if found_fmt {
+            true
+        } else {
+            found_fmt = *x == subcommand;
+            *x != subcommand
+        }
+    });
+
+    let opts = Opts::parse_from(args);
+    let strategy = FmtStrategy::from_opts(&opts);
+
+    let metadata = get_cargo_metadata();
+    let journal_dir = journal::journal_dir(metadata.target_directory.as_std_path());
+    if opts.undo {
+        journal::undo(&journal_dir);
+        return;
+    }
+
+    let config_file =
+        ConfigFile::load(metadata.workspace_root.as_std_path()).unwrap_or_else(|err| {
+            eprintln!("error: {err}");
+            process::exit(1);
+        });
+    formatter.register_config_file(&config_file);
+
+    let packages = get_packages(metadata, &strategy);
+    formatter.register_inferred_shapes(&infer_shapes(&packages));
+    let formatter = &formatter;
+
+    if opts.emit_mode == EmitMode::Json {
+        report_workspace(formatter, &packages, &opts);
+        return;
+    }
+
+    let mut output = Output {
+        journal_dir,
+        journal: None,
+        backup: opts.backup,
+    };
+
+    if opts.staged {
+        format_staged(formatter, &packages, &opts, &mut output);
+    } else {
+        format_workspace(formatter, &packages, &opts, &mut output);
+    }
+}
+
+/// Writes formatted files
+struct Output {
+    journal_dir: PathBuf,
+    /// Created on the first write, such that a run that writes nothing keeps the journal of the
+    /// previous run
+    journal: Option<Journal>,
+    backup: bool,
+}
+
+impl Output {
+    fn journal(&mut self) -> &mut Journal {
+        self.journal
+            .get_or_insert_with(|| Journal::create(&self.journal_dir))
+    }
+
+    fn write(&mut self, file: &Path, original: &str, formatted: &str) {
+        self.journal().record(file, original, formatted);
+        if self.backup {
+            let mut backup_file = file.as_os_str().to_owned();
+            backup_file.push(".plsfmt.bak");
+            fs::write(backup_file, original).unwrap();
+        }
+        crate::write_atomic(file, formatted).unwrap();
+    }
+
+    /// Writes the formatted content of a staged file to the git index
+    fn update_index(&mut self, toplevel: &Path, entry: &IndexEntry, formatted: &str) {
+        let object = git::update_index(toplevel, &entry.mode, &entry.path, formatted);
+        self.journal()
+            .record_index(toplevel, &entry.mode, &entry.path, &entry.object, &object);
+    }
+}
+
+fn format_workspace(formatter: &Formatter, packages: &[Package], opts: &Opts, output: &mut Output) {
+    let files = selected_files(packages, opts);
+
+    check_version_control(opts, &files);
+
+    for file in &files {
+        format_file(formatter, file, output);
+    }
+}
+
+#[derive(Serialize)]
+struct FileReport {
+    name: PathBuf,
+    #[serde(flatten)]
+    report: FormatReport,
+}
+
+fn report_workspace(formatter: &Formatter, packages: &[Package], opts: &Opts) {
+    let reports: Vec<FileReport> = selected_files(packages, opts)
+        .into_iter()
+        .map(|file| {
+            let content = fs::read_to_string(&file).unwrap();
+            let (_, report) = formatter.format_str_with_report(&content).unwrap();
+            FileReport { name: file, report }
+        })
+        .collect();
+
+    println!("{}", serde_json::to_string(&reports).unwrap());
+}
+
+/// Shapes of the macros defined in all source files of the packages, including files not
+/// selected for formatting
+fn infer_shapes(packages: &[Package]) -> InferredShapes {
+    let all_files = FileFilter {
+        include: Vec::new(),
+        exclude: Vec::new(),
+        substrings: Vec::new(),
+    };
+
+    let mut shapes = InferredShapes::default();
+    for file in packages
+        .iter()
+        .flat_map(|package| files_in_package(package, &all_files))
+    {
+        let Ok(content) = fs::read_to_string(&file) else {
+            continue;
+        };
+        if let Ok(file) = syn::parse_file(&content) {
+            shapes.add_file(&file);
+        }
+    }
+    shapes
+}
+
+/// Refuses to format files with uncommitted changes, like `cargo fix`, such that all
+/// changes made can be reviewed and reverted with git
+fn check_version_control(opts: &Opts, files: &[PathBuf]) {
+    if opts.allow_dirty {
+        return;
+    }
+
+    let current_dir = env::current_dir().unwrap();
+    let Some(toplevel) = git::try_toplevel(&current_dir) else {
+        if opts.allow_no_vcs {
+            return;
+        }
+        eprintln!(
+            "error: no VCS found for this package and `cargo plsfmt` can potentially perform \
+            destructive changes; if you'd like to suppress this error pass `--allow-no-vcs`"
+        );
+        process::exit(1);
+    };
+    let toplevel = toplevel.canonicalize().unwrap();
+
+    let files: BTreeSet<PathBuf> = files
+        .iter()
+        .filter_map(|file| file.canonicalize().ok())
+        .collect();
+    let status = git::status(&toplevel);
+    let uncommitted: BTreeMap<&str, FileStatus> = status
+        .iter()
+        .filter(|(path, status)| {
+            files.contains(&toplevel.join(path))
+                && !(opts.allow_staged && *status == FileStatus::Staged)
+        })
+        .map(|(path, status)| (path.as_str(), *status))
+        .collect();
+
+    if uncommitted.is_empty() {
+        return;
+    }
+
+    eprintln!(
+        "error: the working directory of this package has uncommitted changes, and \
+        `cargo plsfmt` can potentially perform destructive changes; if you'd like to \
+        suppress this error pass `--allow-dirty`, `--allow-staged`, or commit the changes \
+        to these files:\n"
+    );
+    for (path, status) in uncommitted {
+        let status = match status {
+            FileStatus::Staged => "staged",
+            FileStatus::Dirty => "dirty",
+        };
+        eprintln!("  * {path} ({status})");
+    }
+    process::exit(1);
+}
+
+fn format_staged(formatter: &Formatter, packages: &[Package], opts: &Opts, output: &mut Output) {
+    let files: BTreeSet<PathBuf> = selected_files(packages, opts)
+        .iter()
+        .filter_map(|file| file.canonicalize().ok())
+        .collect();
+
+    let toplevel = git::toplevel().canonicalize().unwrap();
+    // All files are merged before any is written, such that a conflict leaves the index and the
+    // working tree unmodified
+    let mut staged_files = Vec::new();
+    let mut conflicts = Vec::new();
+    for entry in git::staged_entries(&toplevel) {
+        let file = toplevel.join(&entry.path);
+        if !files.contains(&file) {
+            continue;
+        }
+
+        let staged = git::read_blob(&toplevel, &entry.object);
+        let (formatted, report) = formatter.format_str_with_report(&staged).unwrap();
+        cli::warn_failed(&file, &report);
+        if formatted == staged {
+            continue;
+        }
+
+        let working = fs::read_to_string(&file).unwrap();
+        let Some(working_formatted) = (if working == staged {
+            Some(formatted.clone())
+        } else {
+            git::merge(&working, &staged, &formatted)
+        }) else {
+            conflicts.push(entry.path);
+            continue;
+        };
+
+        staged_files.push(StagedFile {
+            entry,
+            file,
+            formatted,
+            working,
+            working_formatted,
+        });
+    }
+
+    if !conflicts.is_empty() {
+        eprintln!("error: formatting the staged version conflicts with unstaged changes in:");
+        for path in &conflicts {
+            eprintln!("  {path}");
+        }
+        eprintln!("no files were formatted");
+        eprintln!("stage or stash the unstaged changes in these files and retry");
+        process::exit(1);
+    }
+
+    for staged_file in staged_files {
+        output.update_index(&toplevel, &staged_file.entry, &staged_file.formatted);
+        if staged_file.working_formatted != staged_file.working {
+            output.write(
+                &staged_file.file,
+                &staged_file.working,
+                &staged_file.working_formatted,
+            );
+        }
+    }
+}
+
+/// Staged file formatted by `--staged`, with the formatting merged into the working tree
+struct StagedFile {
+    entry: IndexEntry,
+    file: PathBuf,
+    /// Formatted staged content
+    formatted: String,
+    working: String,
+    working_formatted: String,
+}
+
+fn format_file(formatter: &Formatter, file: &Path, output: &mut Output) {
+    let content = fs::read_to_string(file).unwrap();
+    let (formatted, report) = formatter.format_str_with_report(&content).unwrap();
+    cli::warn_failed(file, &report);
+    if formatted != content {
+        output.write(file, &content, &formatted);
+    }
+}
+
+/// Files selected by the filter options and explicit file arguments, each listed once by its
+/// canonical path
+fn selected_files(packages: &[Package], opts: &Opts) -> Vec<PathBuf> {
+    let (explicit_files, patterns): (Vec<&String>, Vec<&String>) = opts
+        .patterns
+        .iter()
+        .partition(|pattern| Path::new(pattern).is_file());
+
+    let filter = FileFilter {
+        include: opts.include.iter().map(|glob| parse_glob(glob)).collect(),
+        exclude: opts.exclude.iter().map(|glob| parse_glob(glob)).collect(),
+        substrings: patterns.into_iter().cloned().collect(),
+    };
+
+    let current_dir = env::current_dir().unwrap().canonicalize().unwrap();
+    let mut files = BTreeSet::new();
+    for file in explicit_files {
+        let file = Path::new(file).canonicalize().unwrap();
+        let root = packages
+            .iter()
+            .filter_map(|package| package.manifest_path.parent()?.canonicalize().ok())
+            .filter(|package_dir| file.starts_with(package_dir))
+            .max_by_key(|package_dir| package_dir.components().count())
+            .unwrap_or_else(|| current_dir.clone());
+        let path = file.strip_prefix(&root).unwrap_or(&file);
+        if !filter.excluded(path) {
+            files.insert(file);
+        }
+    }
+    if files.is_empty() || !filter.include.is_empty() || !filter.substrings.is_empty() {
+        files.extend(
+            packages
+                .iter()
+                .flat_map(|package| files_in_package(package, &filter))
+                .filter_map(|file| file.canonicalize().ok()),
+        );
+    }
+    files.into_iter().collect()
+}
+
+fn parse_glob(glob: &str) -> glob::Pattern {
+    glob::Pattern::new(glob).unwrap_or_else(|err| {
+        eprintln!("error: invalid glob pattern `{glob}`: {err}");
+        process::exit(1);
+    })
+}
+
+const MATCH_OPTIONS: glob::MatchOptions = glob::MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+struct FileFilter {
+    include: Vec<glob::Pattern>,
+    exclude: Vec<glob::Pattern>,
+    substrings: Vec<String>,
+}
+
+impl FileFilter {
+    /// Match `path` relative to the package root with the globs, and `src_path` relative to the
+    /// `src` directory of the package with the substrings
+    fn matches(&self, path: &Path, src_path: Option<&str>) -> bool {
+        let included = (self.include.is_empty() && self.substrings.is_empty())
+            || self
+                .include
+                .iter()
+                .any(|pattern| pattern.matches_path_with(path, MATCH_OPTIONS))
+            || self
+                .substrings
+                .iter()
+                .any(|substring| src_path.is_some_and(|str| str.contains(substring)));
+
+        included && !self.excluded(path)
+    }
+
+    /// Match `path` relative to the package root with the exclude globs
+    fn excluded(&self, path: &Path) -> bool {
+        self.exclude
+            .iter()
+            .any(|pattern| pattern.matches_path_with(path, MATCH_OPTIONS))
+    }
+}
+
+/// Source files of the targets in the package
+fn files_in_package(package: &Package, filter: &FileFilter) -> Vec<PathBuf> {
+    let package_dir = package.manifest_path.parent().unwrap().as_std_path();
+
+    let mut files = BTreeSet::new();
+    for target in &package.targets {
+        let src_path = target.src_path.as_std_path();
+        let src_dir = src_path.parent().unwrap();
+        if src_dir == package_dir {
+            // e.g. build.rs, don't recurse into the whole package
+            files.insert(src_path.to_path_buf());
+        } else {
+            files.extend(
+                glob::glob(&format!("{}/**/*.rs", src_dir.display()))
+                    .expect("Failed to read source directory")
+                    .filter_map(Result::ok),
+            );
+        }
+    }
+
+    let src_dir = package_dir.join("src");
+    files
+        .into_iter()
+        .filter(|file| {
+            let src_path = file
+                .to_str()
+                .zip(src_dir.to_str())
+                .and_then(|(file, src_dir)| file.strip_prefix(src_dir));
+            file.strip_prefix(package_dir)
+                .is_ok_and(|relative| filter.matches(relative, src_path))
+        })
+        .collect()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum FmtStrategy {
+    Packages(Vec<String>),
+    Root,
+}
+
+impl FmtStrategy {
+    fn from_opts(opts: &Opts) -> FmtStrategy {
+        if let Some(package) = opts.package.as_ref() {
+            FmtStrategy::Packages(vec![package.clone()])
+        } else {
+            FmtStrategy::Root
+        }
+    }
+}
+
+fn get_packages(metadata: cargo_metadata::Metadata, strategy: &FmtStrategy) -> Vec<Package> {
+    match *strategy {
+        FmtStrategy::Root => get_packages_root_only(metadata),
+        FmtStrategy::Packages(ref hitlist) => get_packages_with_hitlist(metadata, hitlist),
+    }
+}
+
+fn get_packages_root_only(metadata: cargo_metadata::Metadata) -> Vec<Package> {
+    let workspace_root_path = PathBuf::from(&metadata.workspace_root)
+        .canonicalize()
+        .unwrap();
+
+    let current_dir = env::current_dir().unwrap().canonicalize().unwrap();
+    let in_workspace_root = workspace_root_path == current_dir;
+    let current_dir_manifest = current_dir.join("Cargo.toml");
+
+    match metadata.packages.len() {
+        1 => metadata.packages,
+        _ => metadata
+            .packages
+            .into_iter()
+            .filter(|p| {
+                in_workspace_root
+                    || PathBuf::from(&p.manifest_path)
+                        .canonicalize()
+                        .unwrap_or_default()
+                        == current_dir_manifest
+            })
+            .collect(),
+    }
+}
+
+fn get_packages_with_hitlist(
+    metadata: cargo_metadata::Metadata,
+    hitlist: &[String],
+) -> Vec<Package> {
+    let mut workspace_hitlist: BTreeSet<&String> = BTreeSet::from_iter(hitlist);
+
+    let packages = metadata
+        .packages
+        .into_iter()
+        .filter(|package| workspace_hitlist.remove(&package.name))
+        .collect();
+
+    if !workspace_hitlist.is_empty() {
+        let package = workspace_hitlist.iter().next().unwrap();
+        panic!("package `{package}` is not a member of the workspace");
+    }
+
+    packages
+}
+
+fn get_cargo_metadata() -> cargo_metadata::Metadata {
+    let mut cmd = cargo_metadata::MetadataCommand::new();
+    cmd.no_deps();
+    cmd.other_options(vec![String::from("--offline")]);
+    cmd.exec().unwrap()
+}
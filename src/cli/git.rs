@@ -0,0 +1,229 @@
+use std::ffi::OsStr;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+use std::{env, fs};
+
+/// Entry in the git index
+pub struct IndexEntry {
+    pub mode: String,
+    pub object: String,
+    /// Path relative to the repository root
+    pub path: String,
+}
+
+/// Uncommitted state of a file in the working tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileStatus {
+    /// Changes in the index only
+    Staged,
+    /// Unstaged changes or untracked
+    Dirty,
+}
+
+pub fn toplevel() -> PathBuf {
+    let out = git(["rev-parse", "--show-toplevel"], None);
+    PathBuf::from(String::from_utf8(out).unwrap().trim_end())
+}
+
+/// Repository root of the working tree containing `dir`, or `None` if it is not in a git repository
+pub fn try_toplevel(dir: &Path) -> Option<PathBuf> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["rev-parse", "--show-toplevel"])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| PathBuf::from(String::from_utf8(output.stdout).unwrap().trim_end()))
+}
+
+/// Files with uncommitted changes, by path relative to the repository root
+pub fn status(toplevel: &Path) -> Vec<(String, FileStatus)> {
+    let out = git_in(
+        toplevel,
+        ["status", "--porcelain", "-z", "--untracked-files=all"],
+        None,
+    );
+
+    let mut entries = Vec::new();
+    let mut lines = split_nul(&out);
+    while let Some(line) = lines.next() {
+        let (xy, path) = line.split_at(3);
+        let mut xy = xy.chars();
+        let (index, worktree) = (xy.next().unwrap(), xy.next().unwrap());
+        if index == 'R' || index == 'C' {
+            // source path of rename or copy
+            lines.next();
+        }
+        let status = if worktree == ' ' {
+            FileStatus::Staged
+        } else {
+            FileStatus::Dirty
+        };
+        entries.push((path.to_string(), status));
+    }
+    entries
+}
+
+/// Index entries for files that are added, copied, modified or renamed in the index compared to
+/// `HEAD`
+pub fn staged_entries(toplevel: &Path) -> Vec<IndexEntry> {
+    let names = git_in(
+        toplevel,
+        [
+            "diff",
+            "--cached",
+            "--name-only",
+            "-z",
+            "--diff-filter=ACMR",
+        ],
+        None,
+    );
+    let paths: Vec<&str> = split_nul(&names).collect();
+    if paths.is_empty() {
+        return Vec::new();
+    }
+
+    let mut args = vec!["ls-files", "--stage", "-z", "--"];
+    args.extend(paths);
+    let stage = git_in(toplevel, args, None);
+
+    split_nul(&stage)
+        .map(|line| {
+            let (info, path) = line.split_once('\t').unwrap();
+            let mut info = info.split(' ');
+            IndexEntry {
+                mode: info.next().unwrap().to_string(),
+                object: info.next().unwrap().to_string(),
+                path: path.to_string(),
+            }
+        })
+        .collect()
+}
+
+pub fn read_blob(toplevel: &Path, object: &str) -> String {
+    String::from_utf8(git_in(toplevel, ["cat-file", "blob", object], None)).unwrap()
+}
+
+/// Writes `content` as a blob and points the index entry for `path` at it. Returns the object
+/// name of the blob.
+pub fn update_index(toplevel: &Path, mode: &str, path: &str, content: &str) -> String {
+    let object = git_in(
+        toplevel,
+        ["hash-object", "-w", "--no-filters", "--stdin"],
+        Some(content.as_bytes()),
+    );
+    let object = String::from_utf8(object).unwrap().trim_end().to_string();
+    set_index_entry(toplevel, mode, &object, path);
+    object
+}
+
+/// Points the index entry for `path` at the existing blob `object`
+pub fn set_index_entry(toplevel: &Path, mode: &str, object: &str, path: &str) {
+    git_in(
+        toplevel,
+        [
+            "update-index",
+            "--cacheinfo",
+            &format!("{mode},{object},{path}"),
+        ],
+        None,
+    );
+}
+
+/// Object name of the blob in the index entry for `path`, `None` if it is not in the index
+pub fn index_object(toplevel: &Path, path: &str) -> Option<String> {
+    let stage = git_in(toplevel, ["ls-files", "--stage", "-z", "--", path], None);
+    let line = split_nul(&stage).next()?;
+    let (info, _) = line.split_once('\t')?;
+    info.split(' ').nth(1).map(str::to_string)
+}
+
+/// Three-way merge of the edit `base` -> `other` into `current`. Returns `None` if the
+/// merge conflicts. `git merge-file` exits with the number of conflicts, capped at 127, and with
+/// a negative status on errors.
+pub fn merge(current: &str, base: &str, other: &str) -> Option<String> {
+    let dir = env::temp_dir().join(format!("plsfmt-merge-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let files = [("current", current), ("base", base), ("other", other)].map(|(name, content)| {
+        let file = dir.join(name);
+        fs::write(&file, content).unwrap();
+        file
+    });
+
+    let output = Command::new("git")
+        .arg("merge-file")
+        .arg("-p")
+        .arg("--quiet")
+        .args(&files)
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    match output.status.code() {
+        Some(0) => Some(String::from_utf8(output.stdout).unwrap()),
+        Some(1..=127) => None,
+        _ => fail("merge-file", &output.stderr),
+    }
+}
+
+fn git<I, S>(args: I, stdin: Option<&[u8]>) -> Vec<u8>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    run(Command::new("git").args(args), stdin)
+}
+
+fn git_in<I, S>(dir: &Path, args: I, stdin: Option<&[u8]>) -> Vec<u8>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    run(Command::new("git").arg("-C").arg(dir).args(args), stdin)
+}
+
+fn run(cmd: &mut Command, stdin: Option<&[u8]>) -> Vec<u8> {
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap_or_else(|err| {
+            eprintln!("error: failed to run git: {err}");
+            process::exit(1);
+        });
+
+    let mut child_stdin = child.stdin.take().unwrap();
+    if let Some(stdin) = stdin {
+        child_stdin.write_all(stdin).unwrap();
+    }
+    drop(child_stdin);
+
+    let output = child.wait_with_output().unwrap();
+    if !output.status.success() {
+        let args: Vec<_> = cmd.get_args().map(|arg| arg.to_string_lossy()).collect();
+        fail(&args.join(" "), &output.stderr);
+    }
+    output.stdout
+}
+
+fn fail(command: &str, stderr: &[u8]) -> ! {
+    eprintln!(
+        "error: `git {}` failed: {}",
+        command,
+        String::from_utf8_lossy(stderr).trim_end()
+    );
+    process::exit(1);
+}
+
+fn split_nul(bytes: &[u8]) -> impl Iterator<Item = &str> {
+    bytes
+        .split(|&byte| byte == 0)
+        .filter(|part| !part.is_empty())
+        .map(|part| std::str::from_utf8(part).unwrap())
+}
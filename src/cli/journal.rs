@@ -0,0 +1,160 @@
+use crate::cli::git;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Journal of the files modified by the most recent formatting run. The journal directory
+/// contains an `index` file with a line `<n>\t<path>` for each modified file, and the content
+/// of the file before and after formatting in `<n>.orig` and `<n>.new`. Entries of the git
+/// index updated in `--staged` mode are recorded in a `git-index` file with a line
+/// `<mode>\t<original object>\t<formatted object>\t<repository root>\t<path>` for each entry.
+pub struct Journal {
+    dir: PathBuf,
+    index: File,
+    git_index: File,
+    entries: usize,
+}
+
+pub fn journal_dir(target_dir: &Path) -> PathBuf {
+    target_dir.join("plsfmt").join("journal")
+}
+
+impl Journal {
+    /// Starts a new journal, replacing the journal of the previous run
+    pub fn create(dir: &Path) -> Self {
+        if dir.exists() {
+            fs::remove_dir_all(dir).unwrap();
+        }
+        fs::create_dir_all(dir).unwrap();
+
+        Self {
+            dir: dir.to_path_buf(),
+            index: File::create(dir.join("index")).unwrap(),
+            git_index: File::create(dir.join("git-index")).unwrap(),
+            entries: 0,
+        }
+    }
+
+    /// Records the change of `file`. Must be called before the file is overwritten.
+    pub fn record(&mut self, file: &Path, original: &str, formatted: &str) {
+        let n = self.entries;
+        fs::write(self.dir.join(format!("{n}.orig")), original).unwrap();
+        fs::write(self.dir.join(format!("{n}.new")), formatted).unwrap();
+        let file = file.canonicalize().unwrap();
+        writeln!(self.index, "{}\t{}", n, file.display()).unwrap();
+        self.index.sync_all().unwrap();
+        self.entries += 1;
+    }
+
+    /// Records the change of the git index entry for `path` from the blob `original` to the
+    /// blob `formatted`. The blobs are kept in the object database by git.
+    pub fn record_index(
+        &mut self,
+        toplevel: &Path,
+        mode: &str,
+        path: &str,
+        original: &str,
+        formatted: &str,
+    ) {
+        writeln!(
+            self.git_index,
+            "{}\t{}\t{}\t{}\t{}",
+            mode,
+            original,
+            formatted,
+            toplevel.display(),
+            path
+        )
+        .unwrap();
+        self.git_index.sync_all().unwrap();
+    }
+}
+
+/// Index entry recorded with [`Journal::record_index`]
+struct IndexRecord {
+    mode: String,
+    original: String,
+    formatted: String,
+    toplevel: PathBuf,
+    path: String,
+}
+
+/// Restores all files and git index entries modified by the most recent run. Refuses to restore
+/// anything if one of them has changed since it was formatted.
+pub fn undo(dir: &Path) {
+    let Ok(index) = fs::read_to_string(dir.join("index")) else {
+        eprintln!("error: no formatting run to undo");
+        process::exit(1);
+    };
+
+    let entries: Vec<(&str, PathBuf)> = index
+        .lines()
+        .map(|line| {
+            let (n, file) = line.split_once('\t').unwrap();
+            (n, PathBuf::from(file))
+        })
+        .collect();
+
+    let index_records: Vec<IndexRecord> = fs::read_to_string(dir.join("git-index"))
+        .unwrap_or_default()
+        .lines()
+        .map(|line| {
+            let mut fields = line.splitn(5, '\t').map(str::to_string);
+            let mut field = || fields.next().unwrap();
+            IndexRecord {
+                mode: field(),
+                original: field(),
+                formatted: field(),
+                toplevel: PathBuf::from(field()),
+                path: field(),
+            }
+        })
+        .collect();
+
+    let mut changed: Vec<String> = entries
+        .iter()
+        .filter(|(n, file)| {
+            let formatted = fs::read_to_string(dir.join(format!("{n}.new"))).unwrap();
+            fs::read_to_string(file).ok().as_ref() != Some(&formatted)
+        })
+        .map(|(_, file)| file.display().to_string())
+        .collect();
+    changed.extend(
+        index_records
+            .iter()
+            .filter(|record| {
+                git::index_object(&record.toplevel, &record.path).as_ref()
+                    != Some(&record.formatted)
+            })
+            .map(|record| format!("{} (index)", record.toplevel.join(&record.path).display())),
+    );
+
+    if !changed.is_empty() {
+        eprintln!("error: refusing to undo since files have changed after they were formatted:");
+        for file in changed {
+            eprintln!("  {file}");
+        }
+        process::exit(1);
+    }
+
+    for (n, file) in &entries {
+        let original = fs::read_to_string(dir.join(format!("{n}.orig"))).unwrap();
+        crate::write_atomic(file, &original).unwrap();
+        println!("restored {}", file.display());
+    }
+    for record in &index_records {
+        git::set_index_entry(
+            &record.toplevel,
+            &record.mode,
+            &record.original,
+            &record.path,
+        );
+        println!(
+            "restored {} in the index",
+            record.toplevel.join(&record.path).display()
+        );
+    }
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,17 @@
+use crate::{FormatReport, SkipReason};
+use std::path::Path;
+
+pub mod cargo_plsfmt;
+mod git;
+mod journal;
+pub mod plsfmt;
+
+/// Prints a warning for each macro invocation in `file` that was skipped because its formatter
+/// failed
+fn warn_failed(file: &Path, report: &FormatReport) {
+    for mac in &report.macros {
+        if let Some(SkipReason::Failed(message)) = &mac.skipped {
+            eprintln!("warning: {}:{}: {message}", file.display(), mac.start.line);
+        }
+    }
+}
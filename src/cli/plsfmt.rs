@@ -0,0 +1,85 @@
+use crate::cli;
+use crate::macros::infer::InferredShapes;
+use crate::{ConfigFile, FormatReport, Formatter};
+use clap::{Parser, ValueEnum};
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+#[clap(rename_all = "kebab-case")]
+enum EmitMode {
+    /// Emits to files.
+    Files,
+    /// Writes the output to stdout.
+    Stdout,
+    /// Writes a JSON report of the formatted macros to stdout.
+    Json,
+}
+
+#[derive(Parser)]
+struct Args {
+    #[arg(long = "emit", value_enum, default_value_t = EmitMode::Files)]
+    emit_mode: EmitMode,
+
+    files: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct FileReport<'a> {
+    name: &'a str,
+    #[serde(flatten)]
+    report: FormatReport,
+}
+
+/// Runs `plsfmt` with the given formatter. Macros configured in `plsfmt.toml` in the current
+/// directory and macros with shapes inferred from `macro_rules!` definitions in the files are
+/// registered in addition.
+pub fn run(mut formatter: Formatter) {
+    let args = Args::parse();
+
+    let config_file = ConfigFile::load(Path::new(".")).unwrap_or_else(|err| {
+        eprintln!("error: {err}");
+        process::exit(1);
+    });
+    formatter.register_config_file(&config_file);
+
+    let mut shapes = InferredShapes::default();
+    for filename in &args.files {
+        let content = fs::read_to_string(filename).unwrap();
+        if let Ok(file) = syn::parse_file(&content) {
+            shapes.add_file(&file);
+        }
+    }
+    formatter.register_inferred_shapes(&shapes);
+
+    let mut reports = Vec::new();
+    for filename in &args.files {
+        let content = fs::read_to_string(filename).unwrap();
+
+        let (formatted, report) = formatter.format_str_with_report(&content).unwrap();
+        cli::warn_failed(Path::new(filename), &report);
+
+        match args.emit_mode {
+            EmitMode::Files => {
+                if formatted != content {
+                    crate::write_atomic(Path::new(filename), &formatted).unwrap();
+                }
+            }
+            EmitMode::Stdout => {
+                print!("{}", formatted);
+            }
+            EmitMode::Json => {
+                reports.push(FileReport {
+                    name: filename,
+                    report,
+                });
+            }
+        }
+    }
+
+    if let EmitMode::Json = args.emit_mode {
+        println!("{}", serde_json::to_string(&reports).unwrap());
+    }
+}
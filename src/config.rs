@@ -0,0 +1,137 @@
+use crate::error::Error;
+use crate::layout::PRETTYPLEASE_MARGIN;
+use crate::macros::grammar::Grammar;
+use crate::macros::plugin::Plugin;
+use crate::macros::shape::MacroShape;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::{fs, io};
+
+/// Formatting configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// Maximum width of each line. Widths larger than the prettyplease margin of 89 are treated
+    /// as 89, and prettyplease always leaves at least 60 columns after the indentation.
+    pub max_width: usize,
+    /// Number of spaces per indentation level
+    pub tab_spaces: usize,
+    /// Line endings of the whole formatted file, including code outside the formatted macros
+    pub newline_style: NewlineStyle,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_width: PRETTYPLEASE_MARGIN,
+            tab_spaces: 4,
+            newline_style: NewlineStyle::Auto,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NewlineStyle {
+    /// Use the line ending of the first line in the file
+    #[default]
+    Auto,
+    /// `\r\n` on Windows, `\n` elsewhere
+    Native,
+    /// `\n`
+    Unix,
+    /// `\r\n`
+    Windows,
+}
+
+impl NewlineStyle {
+    /// Converts all line endings in `text` to this style. `original` is the unformatted file
+    /// content, used to detect the line ending for [`NewlineStyle::Auto`].
+    pub(crate) fn apply(self, original: &str, text: String) -> String {
+        let crlf = self.is_crlf(original);
+
+        let text = if text.contains('\r') {
+            text.replace("\r\n", "\n")
+        } else {
+            text
+        };
+        if crlf {
+            text.replace('\n', "\r\n")
+        } else {
+            text
+        }
+    }
+
+    /// Whether line endings are `\r\n` for a file with the given original content
+    pub(crate) fn is_crlf(self, original: &str) -> bool {
+        match self {
+            NewlineStyle::Auto => original
+                .find('\n')
+                .is_some_and(|index| original[..index].ends_with('\r')),
+            NewlineStyle::Native => cfg!(windows),
+            NewlineStyle::Unix => false,
+            NewlineStyle::Windows => true,
+        }
+    }
+}
+
+/// Content of a `plsfmt.toml` file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    /// Format macros without a configured or built-in formatter if their tokens parse as comma
+    /// separated expressions, or as statements or items between braces
+    #[serde(default)]
+    pub fallback: bool,
+    /// Shapes of macros by macro name, e.g. `my_router = "match-arms"`
+    #[serde(default)]
+    pub macros: BTreeMap<String, MacroShape>,
+    /// Grammars of macros by macro name, see [`Grammar`]
+    #[serde(default)]
+    pub grammars: BTreeMap<String, Grammar>,
+    /// Macro paths mapped to the path of a macro with a known formatter, e.g.
+    /// `"my_runtime::select" = "tokio::select"` for a re-export
+    #[serde(default)]
+    pub paths: BTreeMap<String, String>,
+    /// External formatters by macro name, see [`Plugin`]
+    #[serde(default)]
+    pub plugins: BTreeMap<String, Plugin>,
+}
+
+impl ConfigFile {
+    pub const FILE_NAME: &str = "plsfmt.toml";
+
+    pub fn parse(content: &str) -> Result<Self, Error> {
+        toml::from_str(content).map_err(Error::Config)
+    }
+
+    /// Reads `plsfmt.toml` in `dir`. Returns the default configuration if the file does not
+    /// exist.
+    pub fn load(dir: &Path) -> Result<Self, Error> {
+        match fs::read_to_string(dir.join(Self::FILE_NAME)) {
+            Ok(content) => Self::parse(&content),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ConfigFile;
+    use crate::macros::shape::MacroShape;
+
+    #[test]
+    fn test_parse_config_file() {
+        let content = r#"
+[macros]
+my_router = "match-arms"
+define_events = "items"
+"#;
+
+        let config_file = ConfigFile::parse(content).unwrap();
+
+        assert_eq!(config_file.macros["my_router"], MacroShape::MatchArms);
+        assert_eq!(config_file.macros["define_events"], MacroShape::Items);
+        assert!(ConfigFile::parse("[macros]\nmy_router = \"unknown\"").is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use std::ops::Range;
+
+/// Changed hunk between two texts, as byte ranges of whole lines
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+/// Line based diff of `old` and `new` using the longest common subsequence of lines. The lines
+/// include their line terminator.
+pub fn diff_lines(old: &str, new: &str) -> Vec<Hunk> {
+    let old_lines = lines(old);
+    let new_lines = lines(new);
+    let matches = longest_common_subsequence(
+        &old_lines
+            .iter()
+            .map(|line| &old[line.clone()])
+            .collect::<Vec<_>>(),
+        &new_lines
+            .iter()
+            .map(|line| &new[line.clone()])
+            .collect::<Vec<_>>(),
+    );
+
+    let line_start = |lines: &[Range<usize>], index: usize, text: &str| {
+        lines.get(index).map_or(text.len(), |line| line.start)
+    };
+
+    let mut hunks = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    for (next_i, next_j) in matches
+        .into_iter()
+        .chain([(old_lines.len(), new_lines.len())])
+    {
+        if next_i > i || next_j > j {
+            hunks.push(Hunk {
+                old: line_start(&old_lines, i, old)..line_start(&old_lines, next_i, old),
+                new: line_start(&new_lines, j, new)..line_start(&new_lines, next_j, new),
+            });
+        }
+        (i, j) = (next_i + 1, next_j + 1);
+    }
+
+    hunks
+}
+
+/// Index pairs of the matching elements in a longest common subsequence of `old` and `new`
+pub fn longest_common_subsequence<T: PartialEq>(old: &[T], new: &[T]) -> Vec<(usize, usize)> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    // lcs[i * columns + j] is the length of the longest common subsequence of old_middle[i..]
+    // and new_middle[j..]
+    let columns = new_middle.len() + 1;
+    let mut lcs = vec![0usize; (old_middle.len() + 1) * columns];
+    for i in (0..old_middle.len()).rev() {
+        for j in (0..new_middle.len()).rev() {
+            lcs[i * columns + j] = if old_middle[i] == new_middle[j] {
+                lcs[(i + 1) * columns + j + 1] + 1
+            } else {
+                lcs[(i + 1) * columns + j].max(lcs[i * columns + j + 1])
+            };
+        }
+    }
+
+    let mut matches: Vec<(usize, usize)> = (0..prefix).map(|index| (index, index)).collect();
+    let (mut i, mut j) = (0, 0);
+    while i < old_middle.len() && j < new_middle.len() {
+        if old_middle[i] == new_middle[j] {
+            matches.push((prefix + i, prefix + j));
+            i += 1;
+            j += 1;
+        } else if lcs[i * columns + j + 1] >= lcs[(i + 1) * columns + j] {
+            j += 1;
+        } else {
+            i += 1;
+        }
+    }
+    matches
+        .extend((0..suffix).map(|index| (old.len() - suffix + index, new.len() - suffix + index)));
+
+    matches
+}
+
+/// Byte ranges of the lines in `text`, including the line terminator
+fn lines(text: &str) -> Vec<Range<usize>> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for line in text.split_inclusive('\n') {
+        lines.push(start..start + line.len());
+        start += line.len();
+    }
+    lines
+}
+
+#[cfg(test)]
+mod test {
+    use crate::diff::{Hunk, diff_lines};
+
+    #[test]
+    fn test_diff_equal() {
+        assert_eq!(diff_lines("a\nb\n", "a\nb\n"), vec![]);
+    }
+
+    #[test]
+    fn test_diff_changed_line() {
+        let old = "a\nb\nc\n";
+        let new = "a\nx\nc\n";
+
+        let hunks = diff_lines(old, new);
+
+        assert_eq!(
+            hunks,
+            vec![Hunk {
+                old: 2..4,
+                new: 2..4
+            }]
+        );
+    }
+
+    #[test]
+    fn test_diff_inserted_and_removed_lines() {
+        let old = "a\nb\nc\nd";
+        let new = "a\nx\nb\nd";
+
+        let hunks = diff_lines(old, new);
+
+        assert_eq!(
+            hunks,
+            vec![
+                Hunk {
+                    old: 2..2,
+                    new: 2..4
+                },
+                Hunk {
+                    old: 4..6,
+                    new: 6..6
+                }
+            ]
+        );
+    }
+}
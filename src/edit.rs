@@ -0,0 +1,82 @@
+use crate::diff;
+use crate::replace::Replacement;
+use serde::Serialize;
+use std::ops::Range;
+
+/// Replacement of a byte range in the original content
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TextEdit {
+    pub range: Range<usize>,
+    pub new_text: String,
+}
+
+/// Minimal line based edits turning each macro invocation into its replacement
+pub(crate) fn text_edits(content: &str, replacements: &[Replacement]) -> Vec<TextEdit> {
+    replacements
+        .iter()
+        .flat_map(|replacement| {
+            let original = &content[replacement.range.clone()];
+            let offset = replacement.range.start;
+            diff::diff_lines(original, &replacement.text)
+                .into_iter()
+                .map(move |hunk| TextEdit {
+                    range: offset + hunk.old.start..offset + hunk.old.end,
+                    new_text: replacement.text[hunk.new].to_string(),
+                })
+        })
+        .collect()
+}
+
+/// Applies edits with non-overlapping ranges to `content`
+pub fn apply_edits(content: &str, edits: &[TextEdit]) -> String {
+    let mut edits: Vec<&TextEdit> = edits.iter().collect();
+    edits.sort_by_key(|edit| edit.range.start);
+
+    let mut out = String::with_capacity(content.len());
+    let mut cursor = 0;
+    for edit in edits {
+        out.push_str(&content[cursor..edit.range.start]);
+        out.push_str(&edit.new_text);
+        cursor = edit.range.end;
+    }
+    out.push_str(&content[cursor..]);
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use crate::edit::{TextEdit, apply_edits};
+    use crate::{Formatter, assert_eq_text};
+
+    #[test]
+    fn test_text_edits() {
+        let code = r#"
+fn func() {
+    select! {
+        val1 = future1.expr() => a.expr(),
+        val2 = future2.expr() => {
+            b.expr()
+        }
+        val3 = future3.expr() => c.expr(),
+    }
+}
+"#;
+
+        let formatter = Formatter::default();
+        let edits = formatter.text_edits(code).unwrap();
+
+        let start = code.find("        val2").unwrap();
+        let end = code.find("        val3").unwrap();
+        assert_eq!(
+            edits,
+            vec![TextEdit {
+                range: start..end,
+                new_text: "        val2 = future2.expr() => b.expr(),\n".to_string(),
+            }]
+        );
+        assert_eq_text!(
+            apply_edits(code, &edits),
+            formatter.format_str(code).unwrap()
+        );
+    }
+}
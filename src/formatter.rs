@@ -0,0 +1,519 @@
+use crate::config::{Config, ConfigFile};
+use crate::edit::{self, TextEdit};
+use crate::error::Error;
+use crate::macro_factory_binding::MacroRegistry;
+use crate::macro_syntax::{MacroFactory, MacroFormatter};
+use crate::macros::infer::InferredShapes;
+use crate::model::{ParsedMacro, SkipReason, SkippedMacro};
+use crate::position_map::PositionMap;
+use crate::replace;
+use crate::report::FormatReport;
+use crate::resolve::Resolver;
+use proc_macro2::TokenStream;
+use std::borrow::Cow;
+use std::fs;
+use std::path::Path;
+use syn::spanned::Spanned;
+use syn::visit::Visit;
+use syn::{Attribute, Macro, Meta, MetaList, Token, visit};
+
+/// Formats macro invocations in Rust source files. Code outside the macros supported by the
+/// registered macro factories is left untouched, except that the line endings of the whole file
+/// are converted to the configured [`NewlineStyle`](crate::NewlineStyle).
+#[derive(Default)]
+pub struct Formatter {
+    config: Config,
+    registry: MacroRegistry,
+}
+
+#[derive(Default)]
+pub struct FormatterBuilder {
+    config: Config,
+    registry: MacroRegistry,
+}
+
+impl FormatterBuilder {
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Replace the set of enabled macro factories, which defaults to [`MacroRegistry::builtin`]
+    pub fn macros(mut self, registry: MacroRegistry) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    /// Enable an additional macro factory
+    pub fn macro_factory<F: MacroFactory>(mut self) -> Self {
+        self.registry.register::<F>();
+        self
+    }
+
+    /// Enable an additional macro formatter
+    pub fn macro_formatter(mut self, formatter: Box<dyn MacroFormatter>) -> Self {
+        self.registry.register_formatter(formatter);
+        self
+    }
+
+    /// Enable an additional formatter of attribute arguments, see
+    /// [`MacroRegistry::register_attribute_formatter`]
+    pub fn attribute_formatter(mut self, formatter: Box<dyn MacroFormatter>) -> Self {
+        self.registry.register_attribute_formatter(formatter);
+        self
+    }
+
+    /// Enable formatting of macros without a bound formatter, see
+    /// [`MacroRegistry::set_fallback`]
+    pub fn fallback(mut self, fallback: bool) -> Self {
+        self.registry.set_fallback(fallback);
+        self
+    }
+
+    /// Enable formatting of the macros configured in a `plsfmt.toml` file
+    pub fn config_file(mut self, config_file: &ConfigFile) -> Self {
+        self.registry.register_config_file(config_file);
+        self
+    }
+
+    pub fn build(self) -> Formatter {
+        Formatter {
+            config: self.config,
+            registry: self.registry,
+        }
+    }
+}
+
+impl Formatter {
+    pub fn builder() -> FormatterBuilder {
+        FormatterBuilder::default()
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Registers a macro formatter, e.g. for a DSL macro defined in a downstream crate.
+    /// Formatters bound to the same name as an already registered one are tried after it.
+    pub fn register(&mut self, formatter: Box<dyn MacroFormatter>) {
+        self.registry.register_formatter(formatter);
+    }
+
+    /// Enables formatting of the macros configured in a `plsfmt.toml` file
+    pub fn register_config_file(&mut self, config_file: &ConfigFile) {
+        self.registry.register_config_file(config_file);
+    }
+
+    /// Enables formatting of macros with the shapes inferred from their `macro_rules!`
+    /// definitions. Invocations that do not parse with the inferred shape are left untouched.
+    pub fn register_inferred_shapes(&mut self, shapes: &InferredShapes) {
+        self.registry.register_inferred_shapes(shapes);
+    }
+
+    /// Formats the content of a Rust source file
+    pub fn format_str(&self, content: &str) -> Result<String, Error> {
+        self.format_str_with_report(content)
+            .map(|(formatted, _)| formatted)
+    }
+
+    /// Formats the content of a Rust source file and reports how each macro invocation was
+    /// formatted
+    pub fn format_str_with_report(&self, content: &str) -> Result<(String, FormatReport), Error> {
+        let file = syn::parse_file(content)?;
+
+        let visitor = self.visit(&file);
+
+        let (replacements, mut skipped_macros) =
+            replace::replacements(content, visitor.parsed_macros, &self.config);
+        skipped_macros.extend(visitor.skipped_macros);
+        let report = FormatReport::new(content, &replacements, &skipped_macros);
+        let formatted = replace::replace(content, &replacements);
+        Ok((self.config.newline_style.apply(content, formatted), report))
+    }
+
+    /// Formats the content of a Rust source file and returns a map from offsets in the
+    /// original content to offsets in the formatted content
+    pub fn format_str_with_position_map(
+        &self,
+        content: &str,
+    ) -> Result<(String, PositionMap), Error> {
+        let file = syn::parse_file(content)?;
+
+        let visitor = self.visit(&file);
+
+        let (replacements, _) = replace::replacements(content, visitor.parsed_macros, &self.config);
+        let replaced = replace::replace(content, &replacements);
+        let position_map =
+            PositionMap::new(content, &replacements, &replaced, self.config.newline_style);
+        Ok((
+            self.config.newline_style.apply(content, replaced),
+            position_map,
+        ))
+    }
+
+    /// Minimal line based edits that format the macro invocations in the content of a Rust
+    /// source file, for editor integrations that want to preserve cursor positions, folds and
+    /// undo history. Unlike [`Formatter::format_str`], line endings outside the formatted
+    /// macros are not converted to the configured newline style.
+    pub fn text_edits(&self, content: &str) -> Result<Vec<TextEdit>, Error> {
+        let file = syn::parse_file(content)?;
+
+        let visitor = self.visit(&file);
+
+        let (replacements, _) = replace::replacements(content, visitor.parsed_macros, &self.config);
+        Ok(edit::text_edits(content, &replacements))
+    }
+
+    /// Reads and formats the Rust source file at `path`. The file is not modified.
+    pub fn format_file_at(&self, path: impl AsRef<Path>) -> Result<String, Error> {
+        let content = fs::read_to_string(path)?;
+        self.format_str(&content)
+    }
+
+    /// Pretty prints the tokens of a Rust source file, e.g. generated code, with prettyplease,
+    /// and formats the macro invocations in it
+    pub fn format_tokens(&self, tokens: TokenStream) -> Result<String, Error> {
+        let file: syn::File = syn::parse2(tokens)?;
+        self.format_str(&prettyplease::unparse(&file))
+    }
+
+    /// The macro invocations in `file` that can be formatted
+    pub fn parsed_macros<'ast>(&self, file: &'ast syn::File) -> Vec<ParsedMacro<'ast>> {
+        self.visit(file).parsed_macros
+    }
+
+    fn visit<'ast>(&self, file: &'ast syn::File) -> MacroVisitor<'_, 'ast> {
+        let mut visitor = MacroVisitor {
+            registry: &self.registry,
+            resolver: Resolver::new(file),
+            parsed_macros: Vec::new(),
+            skipped_macros: Vec::new(),
+        };
+        visitor.visit_file(file);
+        visitor
+    }
+}
+
+struct MacroVisitor<'a, 'ast> {
+    registry: &'a MacroRegistry,
+    resolver: Resolver,
+    parsed_macros: Vec<ParsedMacro<'ast>>,
+    skipped_macros: Vec<SkippedMacro<'ast>>,
+}
+
+impl<'ast> visit::Visit<'ast> for MacroVisitor<'_, 'ast> {
+    fn visit_macro(&mut self, mac: &'ast Macro) {
+        if let Some(macro_syntax) = self
+            .registry
+            .parse_macro_syntax_resolved(mac, &self.resolver)
+        {
+            self.parsed_macros.push(ParsedMacro {
+                macro_syntax,
+                syn_macro: Cow::Borrowed(mac),
+            });
+        } else if self.registry.is_bound_resolved(mac, &self.resolver) {
+            self.skipped_macros.push(SkippedMacro {
+                reason: SkipReason::Unparseable,
+                syn_macro: Cow::Borrowed(mac),
+            });
+        }
+    }
+
+    fn visit_attribute(&mut self, attr: &'ast Attribute) {
+        if let Meta::List(list) = &attr.meta {
+            self.visit_attribute_args(list);
+        }
+        visit::visit_attribute(self, attr);
+    }
+}
+
+impl MacroVisitor<'_, '_> {
+    /// The arguments of an attribute are formatted like the tokens of a macro invocation
+    fn visit_attribute_args(&mut self, list: &MetaList) {
+        let mac = Macro {
+            path: list.path.clone(),
+            bang_token: Token![!](list.path.span()),
+            delimiter: list.delimiter.clone(),
+            tokens: list.tokens.clone(),
+        };
+        if let Some(macro_syntax) = self
+            .registry
+            .parse_attribute_syntax_resolved(&mac, &self.resolver)
+        {
+            self.parsed_macros.push(ParsedMacro {
+                macro_syntax,
+                syn_macro: Cow::Owned(mac),
+            });
+        } else if self
+            .registry
+            .is_attribute_bound_resolved(&mac, &self.resolver)
+        {
+            self.skipped_macros.push(SkippedMacro {
+                reason: SkipReason::Unparseable,
+                syn_macro: Cow::Owned(mac),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::macro_factory_binding::MacroRegistry;
+    use crate::macros::select::SelectFactory;
+    use crate::{
+        Config, ConfigFile, Formatter, MacroFactory, MacroFormatter, MacroSyntax, NewlineStyle,
+        assert_eq_text,
+    };
+
+    #[test]
+    fn test_tab_spaces() {
+        let code = r#"
+fn func() {
+  select! {
+    val1 = future1.expr() => {
+      a.expr()
+    }
+  }
+}
+        "#;
+
+        let formatter = Formatter::builder()
+            .config(Config {
+                tab_spaces: 2,
+                ..Config::default()
+            })
+            .build();
+        let formatted = formatter.format_str(code).unwrap();
+
+        let expected_formatted = r#"
+fn func() {
+  select! {
+    val1 = future1.expr() => a.expr(),
+  }
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+
+    #[test]
+    fn test_tab_indentation() {
+        let code = "
+fn func() {
+\tprintln!(\"{} {}\", aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa.expr(), bbbbbbbbbbbbbbbbbbbb);
+\tprintln!(\"{} {}\", aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa.expr(), bbbbbbbbbbbbbbbb);
+}
+";
+
+        let formatted = Formatter::default().format_str(code).unwrap();
+
+        let expected_formatted = "
+fn func() {
+\tprintln!(
+\t    \"{} {}\",
+\t    aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa.expr(),
+\t    bbbbbbbbbbbbbbbbbbbb
+\t);
+\tprintln!(\"{} {}\", aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa.expr(), bbbbbbbbbbbbbbbb);
+}
+";
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+
+    #[test]
+    fn test_max_width() {
+        let code = r#"
+fn func() {
+    select! {
+        val1 = future1.expr() => a.expr().expr().expr().expr().expr().expr(),
+    }
+}
+        "#;
+
+        let formatter = Formatter::builder()
+            .config(Config {
+                max_width: 70,
+                ..Config::default()
+            })
+            .build();
+        let formatted = formatter.format_str(code).unwrap();
+
+        let expected_formatted = r#"
+fn func() {
+    select! {
+        val1 = future1.expr() => {
+            a.expr().expr().expr().expr().expr().expr()
+        }
+    }
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+
+    #[test]
+    fn test_newline_style() {
+        let code = "fn func() {\r\n    select! {\r\n        val1 = future1.expr() => { a.expr() }\r\n    }\r\n}\r\n";
+
+        let formatted = Formatter::default().format_str(code).unwrap();
+        assert_eq_text!(
+            formatted,
+            "fn func() {\r\n    select! {\r\n        val1 = future1.expr() => a.expr(),\r\n    }\r\n}\r\n"
+        );
+
+        let formatter = Formatter::builder()
+            .config(Config {
+                newline_style: NewlineStyle::Unix,
+                ..Config::default()
+            })
+            .build();
+        let formatted = formatter.format_str(code).unwrap();
+        assert_eq_text!(
+            formatted,
+            "fn func() {\n    select! {\n        val1 = future1.expr() => a.expr(),\n    }\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_no_macros_enabled() {
+        let code = r#"
+fn func() {
+    select! {
+        val1 = future1.expr() => { a.expr() }
+    }
+}
+        "#;
+
+        let formatter = Formatter::builder().macros(MacroRegistry::empty()).build();
+        let formatted = formatter.format_str(code).unwrap();
+
+        assert_eq_text!(formatted, code);
+    }
+
+    #[test]
+    fn test_format_tokens() {
+        let tokens = "fn func() { select! { val1 = future1.expr() => { a.expr() } } }"
+            .parse()
+            .unwrap();
+
+        let formatted = Formatter::default().format_tokens(tokens).unwrap();
+
+        let expected_formatted = r#"fn func() {
+    select! {
+        val1 = future1.expr() => a.expr(),
+    }
+}
+"#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+
+    #[test]
+    fn test_register_formatter() {
+        struct DslFormatter;
+
+        impl MacroFormatter for DslFormatter {
+            fn name(&self) -> &str {
+                "dsl_select"
+            }
+
+            fn parse(&self, mac: &syn::Macro) -> Option<Box<dyn MacroSyntax>> {
+                SelectFactory::parse(mac)
+            }
+        }
+
+        let code = r#"
+fn func() {
+    dsl_select! {
+        val1 = future1.expr() => { a.expr() }
+    }
+}
+        "#;
+
+        let mut formatter = Formatter::builder().macros(MacroRegistry::empty()).build();
+        formatter.register(Box::new(DslFormatter));
+        let formatted = formatter.format_str(code).unwrap();
+
+        let expected_formatted = r#"
+fn func() {
+    dsl_select! {
+        val1 = future1.expr() => a.expr(),
+    }
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+
+    #[test]
+    fn test_resolve_macro_paths() {
+        let code = r#"
+use tokio::select as tselect;
+
+fn func() {
+    tselect! {
+        val1 = future1.expr() => { a.expr() }
+    }
+    other::select! {
+        val1 = future1.expr() => { a.expr() }
+    }
+    my_runtime::select! {
+        val1 = future1.expr() => { a.expr() }
+    }
+}
+
+mod local {
+    macro_rules! select {
+        ($($t:tt)*) => {};
+    }
+
+    fn func() {
+        select! {
+            val1 = future1.expr() => { a.expr() }
+        }
+    }
+}
+        "#;
+
+        let config_file = ConfigFile::parse(
+            r#"
+[paths]
+"my_runtime::select" = "tokio::select"
+"#,
+        )
+        .unwrap();
+        let formatter = Formatter::builder().config_file(&config_file).build();
+        let formatted = formatter.format_str(code).unwrap();
+
+        let expected_formatted = r#"
+use tokio::select as tselect;
+
+fn func() {
+    tselect! {
+        val1 = future1.expr() => a.expr(),
+    }
+    other::select! {
+        val1 = future1.expr() => { a.expr() }
+    }
+    my_runtime::select! {
+        val1 = future1.expr() => a.expr(),
+    }
+}
+
+mod local {
+    macro_rules! select {
+        ($($t:tt)*) => {};
+    }
+
+    fn func() {
+        select! {
+            val1 = future1.expr() => { a.expr() }
+        }
+    }
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+}
@@ -0,0 +1,367 @@
+use crate::config::Config;
+use crate::macro_syntax::MacroSyntax;
+use crate::macros::arm::requires_comma_to_be_match_arm;
+use prettyplease::INDENT;
+use prettyplease::fixup::FixupContext;
+use prettyplease::iter::IterDelimited;
+use proc_macro2::{Ident, TokenStream, TokenTree};
+use std::ops::Range;
+use std::str::FromStr;
+use syn::punctuated::Punctuated;
+use syn::{Expr, Lifetime, MacroDelimiter, Pat, Token, Type};
+
+pub use prettyplease::algorithm::Printer;
+
+/// Line width prettyplease lays out to
+pub(crate) const PRETTYPLEASE_MARGIN: usize = 89;
+
+/// Prints with a prettyplease [`Printer`] and adapts the output to the configured max width and
+/// indentation. The closure is given the base indentation to use in the printer.
+///
+/// The printer has a fixed margin and indentation, so a narrower max width is emulated by
+/// shifting everything right and removing the shift again afterward, and the indentation
+/// levels are rescaled to `tab_spaces`. Lines inside multiline literals are left untouched.
+pub fn print(
+    config: &Config,
+    base_indent: isize,
+    print: impl FnOnce(&mut Printer, isize),
+) -> String {
+    print_at(config, base_indent, base_indent, print)
+}
+
+/// Like [`print`], for output whose first line starts at `column` rather than at the base
+/// indentation, e.g. after `let x = my_macro!`
+pub fn print_at(
+    config: &Config,
+    base_indent: isize,
+    column: isize,
+    print: impl FnOnce(&mut Printer, isize),
+) -> String {
+    let shift = PRETTYPLEASE_MARGIN.saturating_sub(config.max_width);
+
+    // The text before the first line is printed as spaces such that it counts towards the width
+    let prefix = (column + shift as isize).max(0) as usize;
+    let mut printer = Printer::new();
+    printer.word(" ".repeat(prefix));
+    print(&mut printer, base_indent + shift as isize);
+    let out = printer.eof().split_off(prefix);
+
+    if shift == 0 && config.tab_spaces == INDENT as usize {
+        out
+    } else {
+        reindent(&out, base_indent.max(0) as usize, shift, config.tab_spaces)
+    }
+}
+
+/// Column of the opening delimiter of a macro invocation, see [`print_at`]
+pub(crate) fn delimiter_column(mac: &syn::Macro) -> isize {
+    mac.delimiter.span().open().start().column as isize
+}
+
+/// Syntax that can be printed with a prettyplease [`Printer`]. Implemented for the syn types
+/// and tokens used in macro grammars, and derived with `#[derive(MacroSyntax)]`.
+pub trait Print {
+    fn print(&self, printer: &mut Printer);
+
+    /// Whether the syntax ends with a block, in which case it is not moved to the next line by
+    /// [`break_before`]
+    fn is_blocklike(&self) -> bool {
+        false
+    }
+}
+
+impl Print for Expr {
+    fn print(&self, printer: &mut Printer) {
+        printer.expr(self, FixupContext::NONE);
+    }
+
+    fn is_blocklike(&self) -> bool {
+        matches!(requires_comma_to_be_match_arm(self), Ok(false))
+    }
+}
+
+impl Print for Pat {
+    fn print(&self, printer: &mut Printer) {
+        printer.pat(self);
+    }
+}
+
+impl Print for Type {
+    fn print(&self, printer: &mut Printer) {
+        printer.ty(self);
+    }
+}
+
+impl Print for Ident {
+    fn print(&self, printer: &mut Printer) {
+        printer.word(self.to_string());
+    }
+}
+
+impl Print for Lifetime {
+    fn print(&self, printer: &mut Printer) {
+        printer.word(self.to_string());
+    }
+}
+
+impl<T: Print> Print for Box<T> {
+    fn print(&self, printer: &mut Printer) {
+        (**self).print(printer);
+    }
+
+    fn is_blocklike(&self) -> bool {
+        (**self).is_blocklike()
+    }
+}
+
+impl<T: Print> Print for Option<T> {
+    fn print(&self, printer: &mut Printer) {
+        if let Some(value) = self {
+            value.print(printer);
+        }
+    }
+
+    fn is_blocklike(&self) -> bool {
+        self.as_ref().is_some_and(Print::is_blocklike)
+    }
+}
+
+macro_rules! print_tokens {
+    ($($token:tt)*) => {
+        $(
+            impl Print for Token![$token] {
+                fn print(&self, printer: &mut Printer) {
+                    printer.word(stringify!($token));
+                }
+            }
+        )*
+    };
+}
+
+print_tokens!(
+    = => -> <- , ; : :: . .. ..= @ | & ! ? + - * / % ^ < > <= >= == != += -= #
+    as async await else for if in let move mut ref return where yield
+);
+
+/// Content of a macro invocation printed between the original delimiters, the
+/// [`MacroSyntax`] implemented with `#[derive(MacroSyntax)]`
+pub struct Delimited<T> {
+    delimiter: MacroDelimiter,
+    /// Column of the opening delimiter
+    column: isize,
+    content: T,
+}
+
+impl<T> Delimited<T> {
+    pub fn new(mac: &syn::Macro, content: T) -> Self {
+        Self {
+            delimiter: mac.delimiter.clone(),
+            column: delimiter_column(mac),
+            content,
+        }
+    }
+}
+
+impl<T: Print> MacroSyntax for Delimited<T> {
+    fn delimiter_replacement(&self, base_indent: isize, config: &Config) -> String {
+        print_at(config, base_indent, self.column, |printer, base_indent| {
+            delimited(printer, base_indent, &self.delimiter, &self.content)
+        })
+    }
+}
+
+/// Prints `content` between braces on indented lines, or between parentheses or brackets on
+/// the same line if it fits, for the content of a macro
+fn delimited(
+    printer: &mut Printer,
+    base_indent: isize,
+    delimiter: &MacroDelimiter,
+    content: &impl Print,
+) {
+    let (open, close) = match delimiter {
+        MacroDelimiter::Paren(_) => ("(", ")"),
+        MacroDelimiter::Brace(_) => ("{", "}"),
+        MacroDelimiter::Bracket(_) => ("[", "]"),
+    };
+    let brace = matches!(delimiter, MacroDelimiter::Brace(_));
+
+    printer.word(open);
+    if brace {
+        printer.neverbreak();
+    }
+    printer.cbox(INDENT + base_indent);
+    if brace {
+        printer.hardbreak_if_nonempty();
+    } else {
+        printer.zerobreak();
+    }
+    content.print(printer);
+    if brace {
+        printer.hardbreak();
+    } else {
+        printer.zerobreak();
+    }
+    printer.offset(-INDENT);
+    printer.end();
+    printer.word(close);
+}
+
+/// Prints the elements on separate lines
+pub fn lines<T: Print>(printer: &mut Printer, elements: &[T]) {
+    for element in elements.iter().delimited() {
+        element.print(printer);
+        if !element.is_last {
+            printer.hardbreak();
+        }
+    }
+}
+
+/// Prints the elements separated by spaces, breaking lines where they do not fit
+pub fn repeated<T: Print>(printer: &mut Printer, elements: &[T]) {
+    for element in elements.iter().delimited() {
+        element.print(printer);
+        if !element.is_last {
+            printer.space();
+        }
+    }
+}
+
+/// Prints the elements with their separators, breaking lines after the separators where they
+/// do not fit
+pub fn separated<T: Print, P: Print>(printer: &mut Printer, elements: &Punctuated<T, P>) {
+    for pair in elements.pairs().delimited() {
+        pair.value().print(printer);
+        if let Some(punct) = pair.punct() {
+            punct.print(printer);
+            if !pair.is_last {
+                printer.space();
+            }
+        }
+    }
+}
+
+/// Prints `value` on the next line, indented, if it does not fit on the current line. `space`
+/// is whether to separate it from the preceding syntax by a space if it fits. Values ending in
+/// a block are always kept on the line.
+pub fn break_before(printer: &mut Printer, value: &impl Print, space: bool) {
+    if value.is_blocklike() {
+        if space {
+            printer.word(" ");
+        }
+        value.print(printer);
+        return;
+    }
+
+    printer.neverbreak();
+    printer.ibox(INDENT);
+    if space {
+        printer.space();
+    } else {
+        printer.zerobreak();
+    }
+    value.print(printer);
+    printer.end();
+}
+
+fn reindent(text: &str, base_indent: usize, shift: usize, tab_spaces: usize) -> String {
+    map_lines(text, |line, out| {
+        let content = line.trim_start_matches(' ');
+        let indent = line.len() - content.len();
+        let indent = indent - indent.min(shift);
+        let relative = indent.saturating_sub(base_indent);
+        let indent = indent.min(base_indent)
+            + relative / INDENT as usize * tab_spaces
+            + relative % INDENT as usize;
+        out.extend(std::iter::repeat_n(' ', indent));
+        out.push_str(content);
+    })
+}
+
+/// Replaces the base indentation of the lines after the first, printed as one space per
+/// character of `indentation`, by `indentation` itself, e.g. to keep tabs
+pub(crate) fn restore_indentation(text: &str, indentation: &str) -> String {
+    let base_indent = " ".repeat(indentation.chars().count());
+    map_lines(text, |line, out| match line.strip_prefix(&base_indent) {
+        Some(content) => {
+            out.push_str(indentation);
+            out.push_str(content);
+        }
+        None => out.push_str(line),
+    })
+}
+
+/// Writes each line after the first that is not inside a multiline literal with `map`, and the
+/// other lines as is
+fn map_lines(text: &str, mut map: impl FnMut(&str, &mut String)) -> String {
+    let literals = multiline_literals(text);
+
+    let mut out = String::with_capacity(text.len());
+    let mut line_start = 0;
+    for (index, line) in text.split('\n').enumerate() {
+        if index > 0 {
+            out.push('\n');
+        }
+        let in_literal = literals
+            .iter()
+            .any(|literal| literal.start < line_start && line_start < literal.end);
+        line_start += line.len() + 1;
+
+        if index == 0 || in_literal {
+            out.push_str(line);
+        } else {
+            map(line, &mut out);
+        }
+    }
+    out
+}
+
+/// Byte ranges of literals spanning multiple lines
+fn multiline_literals(text: &str) -> Vec<Range<usize>> {
+    fn collect(tokens: TokenStream, literals: &mut Vec<Range<usize>>) {
+        for token in tokens {
+            match token {
+                TokenTree::Group(group) => collect(group.stream(), literals),
+                TokenTree::Literal(literal) => {
+                    let span = literal.span();
+                    if span.start().line != span.end().line {
+                        literals.push(span.byte_range());
+                    }
+                }
+                TokenTree::Ident(_) | TokenTree::Punct(_) => {}
+            }
+        }
+    }
+
+    let mut literals = Vec::new();
+    if let Ok(tokens) = TokenStream::from_str(text) {
+        collect(tokens, &mut literals);
+    }
+    literals
+}
+
+#[cfg(test)]
+mod test {
+    use crate::assert_eq_text;
+    use crate::layout::reindent;
+
+    #[test]
+    fn test_reindent() {
+        let text = "{\n            a => {\n                b\n            }\n        }";
+
+        let reindented = reindent(text, 8, 0, 2);
+
+        assert_eq_text!(
+            reindented,
+            "{\n          a => {\n            b\n          }\n        }"
+        );
+    }
+
+    #[test]
+    fn test_reindent_multiline_literal() {
+        let text = "{\n                a => \"x\n    y\",\n            }";
+
+        let reindented = reindent(text, 8, 4, 4);
+
+        assert_eq_text!(reindented, "{\n            a => \"x\n    y\",\n        }");
+    }
+}
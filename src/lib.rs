@@ -1,38 +1,47 @@
+#[cfg(feature = "cli")]
+pub mod cli;
 mod comments;
+mod config;
+mod diff;
+mod edit;
+mod error;
+mod formatter;
+mod layout;
 mod macro_factory_binding;
 mod macro_syntax;
 mod macros;
 mod model;
+mod position_map;
 mod replace;
+mod report;
+mod resolve;
+mod write;
 
-use crate::macro_factory_binding::parse_macro_syntax;
-use crate::model::ParsedMacro;
-use syn::visit::Visit;
-use syn::{Macro, visit};
+pub use crate::config::{Config, ConfigFile, NewlineStyle};
+pub use crate::edit::{TextEdit, apply_edits};
+pub use crate::error::Error;
+pub use crate::formatter::{Formatter, FormatterBuilder};
+pub use crate::macro_factory_binding::MacroRegistry;
+pub use crate::macro_syntax::{MacroFactory, MacroFormatter, MacroSyntax};
+pub use crate::macros::grammar::Grammar;
+pub use crate::macros::infer::InferredShapes;
+pub use crate::macros::plugin::{PROTOCOL_VERSION, Plugin};
+pub use crate::macros::shape::{MacroShape, Separators};
+pub use crate::model::{ParsedMacro, Position, SkipReason};
+pub use crate::position_map::PositionMap;
+pub use crate::report::{FormatReport, MacroReport};
+pub use crate::write::write_atomic;
+pub use plsfmt_derive::MacroSyntax;
 
-#[derive(Default)]
-struct MacroVisitor<'ast> {
-    parsed_macros: Vec<ParsedMacro<'ast>>,
-}
-
-impl<'ast> visit::Visit<'ast> for MacroVisitor<'ast> {
-    fn visit_macro(&mut self, mac: &'ast Macro) {
-        if let Some(macro_syntax) = parse_macro_syntax(mac) {
-            self.parsed_macros.push(ParsedMacro {
-                macro_syntax,
-                syn_macro: mac,
-            });
-        }
-    }
+/// Dependencies of the code generated by `#[derive(MacroSyntax)]`
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::layout::{Delimited, Print, Printer, break_before, lines, repeated, separated};
+    pub use syn;
 }
 
+/// Formats the content of a Rust source file with the default configuration and the built-in
+/// macro factories. Panics if the content is not valid Rust.
 pub fn format_file(content: &str) -> String {
-    let file = syn::parse_file(content).unwrap();
-
-    let mut visitor = MacroVisitor::default();
-    visitor.visit_file(&file);
-
-    // let comments = comments::scan_comments(content);
-
-    replace::replace(content, visitor.parsed_macros)
+    Formatter::default().format_str(content).unwrap()
 }
@@ -1,20 +1,274 @@
-use crate::macro_syntax::{MacroFactory, MacroSyntax};
+use crate::config::ConfigFile;
+use crate::macro_syntax::{MacroFactory, MacroFormatter, MacroSyntax};
+use crate::macros::crossbeam_select::CrossbeamSelectFactory;
+use crate::macros::format::{FORMAT_MACROS, FormatFormatter};
+use crate::macros::futures_select::{FuturesSelectBiasedFactory, FuturesSelectFactory};
+use crate::macros::grammar::GrammarFormatter;
+use crate::macros::infer::InferredShapes;
+use crate::macros::instrument::InstrumentFormatter;
+use crate::macros::join::{JOIN_MACROS, JoinFormatter};
+use crate::macros::log::{LOG_MACROS, LogFormatter};
+use crate::macros::plugin::PluginFormatter;
 use crate::macros::select::SelectFactory;
+use crate::macros::shape::{self, MacroShape, Separators, ShapeFormatter};
+use crate::macros::tracing::{TRACING_EVENTS, TRACING_SPANS, TracingFormatter};
+use crate::resolve::{Resolution, Resolver};
+use proc_macro2::TokenTree;
+use std::collections::BTreeMap;
+use syn::MacroDelimiter;
 
-macro_rules! return_macro_syntax {
-    ($ident:ident, $syn_macro:ident, $supplier_factory:ty) => {
-        if $ident == <$supplier_factory>::name() {
-            if let Some(macro_syntax) = <$supplier_factory>::parse($syn_macro) {
-                return Some(macro_syntax);
+/// Set of macro factories and formatters used to parse macro invocations
+pub struct MacroRegistry {
+    factories: Vec<Box<dyn MacroFormatter>>,
+    /// Formatters of the arguments of attributes, like `#[tracing::instrument(..)]`
+    attributes: Vec<Box<dyn MacroFormatter>>,
+    /// Macro paths mapped to the path of a known macro, e.g. for re-exports
+    path_aliases: BTreeMap<String, String>,
+    /// Whether macros without a bound formatter are formatted with [`FALLBACK_SHAPES`]
+    fallback: bool,
+}
+
+/// Shapes tried in order for macros without a bound formatter when the fallback is enabled
+const FALLBACK_SHAPES: [MacroShape; 3] = [MacroShape::Args, MacroShape::Block, MacroShape::Items];
+
+struct FactoryBinding {
+    name: &'static str,
+    path: Option<&'static str>,
+    parse: fn(&syn::Macro) -> Option<Box<dyn MacroSyntax>>,
+}
+
+impl MacroRegistry {
+    /// Registry without any factories
+    pub fn empty() -> Self {
+        Self {
+            factories: Vec::new(),
+            attributes: Vec::new(),
+            path_aliases: BTreeMap::new(),
+            fallback: false,
+        }
+    }
+
+    /// Registry with all macro factories built into plsfmt
+    pub fn builtin() -> Self {
+        let mut registry = Self::empty();
+        registry.register::<SelectFactory>();
+        registry.register::<FuturesSelectFactory>();
+        registry.register::<FuturesSelectBiasedFactory>();
+        registry.register_path_alias("futures_util::select", "futures::select");
+        registry.register_path_alias("futures_util::select_biased", "futures::select_biased");
+        registry.register::<CrossbeamSelectFactory>();
+        registry.register_path_alias("crossbeam::channel::select", "crossbeam_channel::select");
+        for (name, leading_args) in FORMAT_MACROS {
+            registry.register_formatter(Box::new(FormatFormatter::new(name, leading_args)));
+            registry.register_path_alias(format!("core::{name}"), format!("std::{name}"));
+        }
+        registry.register_path_alias("alloc::format", "std::format");
+        for path in JOIN_MACROS {
+            registry.register_formatter(Box::new(JoinFormatter::new(path)));
+            if let Some(name) = path.strip_prefix("futures::") {
+                registry.register_path_alias(format!("futures_util::{name}"), path);
             }
         }
-    };
+        for (path, level) in TRACING_EVENTS {
+            registry.register_formatter(Box::new(TracingFormatter::event(path, level)));
+        }
+        for (path, level) in TRACING_SPANS {
+            registry.register_formatter(Box::new(TracingFormatter::span(path, level)));
+        }
+        // After the tracing formatters, which are tried first for unresolved `info!` etc.
+        for (path, level) in LOG_MACROS {
+            registry.register_formatter(Box::new(LogFormatter::new(path, level)));
+        }
+        registry.register_attribute_formatter(Box::new(InstrumentFormatter));
+        registry.register_path_alias("tracing_attributes::instrument", "tracing::instrument");
+        registry
+    }
+
+    pub fn register<F: MacroFactory>(&mut self) {
+        self.register_formatter(Box::new(FactoryBinding {
+            name: F::name(),
+            path: F::path(),
+            parse: F::parse,
+        }));
+    }
+
+    /// Registers a formatter after the already registered ones. Formatters bound to the same
+    /// name are tried in registration order.
+    pub fn register_formatter(&mut self, formatter: Box<dyn MacroFormatter>) {
+        self.factories.push(formatter);
+    }
+
+    /// Registers a formatter of the arguments of an attribute, e.g. `#[my_attribute(..)]`. The
+    /// formatter is given a [`syn::Macro`] with the path, delimiter and tokens of the attribute.
+    /// Unlike macro formatters, attribute formatters are not bound to macro invocations.
+    pub fn register_attribute_formatter(&mut self, formatter: Box<dyn MacroFormatter>) {
+        self.attributes.push(formatter);
+    }
+
+    /// Binds invocations of the macro at path `from` to the formatters of the macro at path
+    /// `to`, e.g. for a re-export `my_runtime::select` of `tokio::select`
+    pub fn register_path_alias(&mut self, from: impl Into<String>, to: impl Into<String>) {
+        self.path_aliases.insert(from.into(), to.into());
+    }
+
+    /// Formats invocations of macros without a bound formatter if their tokens parse as comma
+    /// separated expressions, or as statements or items between braces. Other invocations are
+    /// left untouched.
+    pub fn set_fallback(&mut self, fallback: bool) {
+        self.fallback = fallback;
+    }
+
+    /// Registers a formatter for each macro shape, grammar and plugin configured in
+    /// `config_file`, and the configured path aliases. Enables the fallback if configured.
+    pub fn register_config_file(&mut self, config_file: &ConfigFile) {
+        if config_file.fallback {
+            self.set_fallback(true);
+        }
+        self.register_shapes(
+            config_file
+                .macros
+                .iter()
+                .map(|(name, shape)| (name.as_str(), *shape, Separators::Rustfmt)),
+        );
+        for (name, grammar) in &config_file.grammars {
+            self.register_formatter(Box::new(GrammarFormatter::new(name, grammar.clone())));
+        }
+        for (name, plugin) in &config_file.plugins {
+            self.register_formatter(Box::new(PluginFormatter::new(name, plugin)));
+        }
+        for (from, to) in &config_file.paths {
+            self.register_path_alias(from, to);
+        }
+    }
+
+    /// Registers a formatter for each macro shape inferred from `macro_rules!` definitions
+    pub fn register_inferred_shapes(&mut self, shapes: &InferredShapes) {
+        self.register_shapes(shapes.shapes());
+    }
+
+    fn register_shapes<'a>(
+        &mut self,
+        shapes: impl Iterator<Item = (&'a str, MacroShape, Separators)>,
+    ) {
+        for (name, shape, separators) in shapes {
+            self.register_formatter(Box::new(ShapeFormatter::new(name, shape, separators)));
+        }
+    }
+
+    /// Whether a macro factory is bound to the macro. The path of the macro is not resolved
+    /// using `use` items, see [`Formatter::parsed_macros`](crate::Formatter::parsed_macros).
+    pub fn is_bound(&self, syn_macro: &syn::Macro) -> bool {
+        self.is_bound_resolved(syn_macro, &Resolver::default())
+    }
+
+    pub fn parse_macro_syntax(&self, syn_macro: &syn::Macro) -> Option<Box<dyn MacroSyntax>> {
+        self.parse_macro_syntax_resolved(syn_macro, &Resolver::default())
+    }
+
+    pub(crate) fn is_bound_resolved(&self, syn_macro: &syn::Macro, resolver: &Resolver) -> bool {
+        bound_formatters(&self.factories, self.resolve(syn_macro, resolver))
+            .next()
+            .is_some()
+    }
+
+    pub(crate) fn is_attribute_bound_resolved(
+        &self,
+        syn_macro: &syn::Macro,
+        resolver: &Resolver,
+    ) -> bool {
+        bound_formatters(&self.attributes, self.resolve(syn_macro, resolver))
+            .next()
+            .is_some()
+    }
+
+    pub(crate) fn parse_macro_syntax_resolved(
+        &self,
+        syn_macro: &syn::Macro,
+        resolver: &Resolver,
+    ) -> Option<Box<dyn MacroSyntax>> {
+        let mut factories =
+            bound_formatters(&self.factories, self.resolve(syn_macro, resolver)).peekable();
+        if factories.peek().is_none() {
+            return self.parse_fallback(syn_macro);
+        }
+        factories.find_map(|factory| factory.parse(syn_macro))
+    }
+
+    /// Parses the arguments of an attribute, given as a [`syn::Macro`], with the bound
+    /// attribute formatters. There is no fallback for attributes.
+    pub(crate) fn parse_attribute_syntax_resolved(
+        &self,
+        syn_macro: &syn::Macro,
+        resolver: &Resolver,
+    ) -> Option<Box<dyn MacroSyntax>> {
+        bound_formatters(&self.attributes, self.resolve(syn_macro, resolver))
+            .find_map(|formatter| formatter.parse(syn_macro))
+    }
+
+    fn parse_fallback(&self, syn_macro: &syn::Macro) -> Option<Box<dyn MacroSyntax>> {
+        if !self.fallback || syn_macro.path.is_ident("macro_rules") {
+            return None;
+        }
+        // Statements and items only in braces, e.g. `vec![0; 4]` is not a block
+        let brace = matches!(syn_macro.delimiter, MacroDelimiter::Brace(_));
+        // The macro may not accept a trailing comma, or require it
+        let trailing_comma = matches!(
+            syn_macro.tokens.clone().into_iter().last(),
+            Some(TokenTree::Punct(punct)) if punct.as_char() == ','
+        );
+        let separators = if trailing_comma {
+            Separators::Terminated
+        } else {
+            Separators::Between
+        };
+        FALLBACK_SHAPES
+            .into_iter()
+            .filter(|shape| brace || *shape == MacroShape::Args)
+            .find_map(|shape| shape::parse_shape(syn_macro, shape, separators))
+    }
+
+    fn resolve(&self, syn_macro: &syn::Macro, resolver: &Resolver) -> Resolution {
+        match resolver.resolve(&syn_macro.path) {
+            Resolution::Path(path) => {
+                Resolution::Path(self.path_aliases.get(&path).cloned().unwrap_or(path))
+            }
+            resolution => resolution,
+        }
+    }
+}
+
+fn bound_formatters(
+    formatters: &[Box<dyn MacroFormatter>],
+    resolution: Resolution,
+) -> impl Iterator<Item = &dyn MacroFormatter> {
+    formatters
+        .iter()
+        .map(Box::as_ref)
+        .filter(move |factory| match (factory.path(), &resolution) {
+            (None, resolution) | (Some(_), resolution @ Resolution::Unresolved(_)) => {
+                resolution.name() == factory.name()
+            }
+            (Some(path), Resolution::Path(resolved)) => path == resolved,
+            (Some(_), Resolution::Local(_)) => false,
+        })
+}
+
+impl Default for MacroRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
 }
 
-pub fn parse_macro_syntax(syn_macro: &syn::Macro) -> Option<Box<dyn MacroSyntax>> {
-    let ident = syn_macro.path.segments.last().map(|seg| &seg.ident)?;
+impl MacroFormatter for FactoryBinding {
+    fn name(&self) -> &str {
+        self.name
+    }
 
-    return_macro_syntax!(ident, syn_macro, SelectFactory);
+    fn path(&self) -> Option<&str> {
+        self.path
+    }
 
-    None
+    fn parse(&self, mac: &syn::Macro) -> Option<Box<dyn MacroSyntax>> {
+        (self.parse)(mac)
+    }
 }
@@ -1,10 +1,66 @@
+use crate::config::Config;
+use crate::model::SkipReason;
+
+/// Parsed macro invocation that can be formatted
 pub trait MacroSyntax {
-    fn delimiter_replacement(&self, base_indent: isize) -> String;
+    /// Formatted replacement of the macro delimiters and the tokens between them.
+    /// `base_indent` is the indentation of the line where the macro invocation starts, with
+    /// tabs counted as one column. Replacements that change the tokens are not applied, apart
+    /// from the changes allowed by [`MacroSyntax::optional_commas`] and
+    /// [`MacroSyntax::match_arms`].
+    fn delimiter_replacement(&self, base_indent: isize, config: &Config) -> String;
+
+    /// Like [`MacroSyntax::delimiter_replacement`], but may decline to format the invocation or
+    /// fail, e.g. a formatter in an external plugin
+    fn try_delimiter_replacement(
+        &self,
+        base_indent: isize,
+        config: &Config,
+    ) -> Result<String, SkipReason> {
+        Ok(self.delimiter_replacement(base_indent, config))
+    }
+
+    /// Whether the macro accepts a comma before a closing delimiter or after a closing brace
+    /// either way, such that the replacement may add or remove it, e.g. the trailing comma of
+    /// the `println!` arguments
+    fn optional_commas(&self) -> bool {
+        false
+    }
+
+    /// Whether the tokens between the delimiters are match-like arms `... => body`, whose bodies
+    /// the replacement may unwrap from or wrap in braces, and whose commas after the bodies it
+    /// may add or remove
+    fn match_arms(&self) -> bool {
+        false
+    }
 }
 
+/// Parser of invocations of a specific macro
 pub trait MacroFactory {
     /// Name the macro binds to
     fn name() -> &'static str;
 
+    /// Full path of the macro, e.g. `tokio::select`. See [`MacroFormatter::path`].
+    fn path() -> Option<&'static str> {
+        None
+    }
+
     fn parse(mac: &syn::Macro) -> Option<Box<dyn MacroSyntax>>;
 }
+
+/// Object safe counterpart of [`MacroFactory`], for formatters that are registered at runtime,
+/// e.g. from a downstream crate with [`Formatter::register`](crate::Formatter::register)
+pub trait MacroFormatter: Send + Sync {
+    /// Name the macro binds to
+    fn name(&self) -> &str;
+
+    /// Full path of the macro, e.g. `tokio::select`. A formatter with a path is bound to
+    /// invocations that resolve to the path through the `use` items of the file, and to
+    /// invocations by name that cannot be resolved. A formatter without a path is bound to all
+    /// invocations by name, including of macros defined in the file.
+    fn path(&self) -> Option<&str> {
+        None
+    }
+
+    fn parse(&self, mac: &syn::Macro) -> Option<Box<dyn MacroSyntax>>;
+}
@@ -0,0 +1,211 @@
+use prettyplease::algorithm::{BreakToken, Printer};
+use prettyplease::fixup::FixupContext;
+use prettyplease::iter::IterDelimited;
+use prettyplease::{INDENT, expr, stmt};
+use proc_macro2::{TokenStream, TokenTree};
+use quote::ToTokens;
+use syn::parse::{ParseStream, Parser};
+use syn::spanned::Spanned;
+use syn::token::Comma;
+use syn::{Expr, Stmt, Token};
+
+/// Parses the body of a match-like arm after the `=>` and the comma after it, which is
+/// optional after a block and at the end of the input
+pub(crate) fn parse_arm_body(input: ParseStream) -> syn::Result<(Expr, Option<Comma>)> {
+    let body = Expr::parse_with_earlier_boundary_rule(input)?;
+    let comma = if requires_comma_to_be_match_arm(&body)? && !input.is_empty() {
+        Some(input.parse()?)
+    } else {
+        input.parse()?
+    };
+    Ok((body, comma))
+}
+
+/// The tokens of match-like arms `... => body,` with the bodies unwrapped from blocks containing
+/// only an expression and from parentheses, and without the commas after the bodies, such that
+/// the tokens before and after [`arm_body`] are the same
+pub(crate) fn normalized_arms(tokens: TokenStream) -> Option<TokenStream> {
+    let parse = |input: ParseStream| {
+        let mut normalized = TokenStream::new();
+        while !input.is_empty() {
+            if input.peek(Token![=>]) {
+                input.parse::<Token![=>]>()?.to_tokens(&mut normalized);
+                let (body, _) = parse_arm_body(input)?;
+                unwrapped_body(&body).to_tokens(&mut normalized);
+            } else {
+                input.parse::<TokenTree>()?.to_tokens(&mut normalized);
+            }
+        }
+        Ok(normalized)
+    };
+    parse.parse2(tokens).ok()
+}
+
+/// The body of a match-like arm without the blocks containing only an expression and the
+/// parentheses around it
+fn unwrapped_body(mut body: &Expr) -> &Expr {
+    loop {
+        match body {
+            Expr::Block(expr) if expr.attrs.is_empty() && expr.label.is_none() => {
+                let mut stmts = expr.block.stmts.iter();
+                match (stmts.next(), stmts.next()) {
+                    (Some(Stmt::Expr(inner, semi)), None)
+                        if semi.is_none() || stmt::add_semi(inner) =>
+                    {
+                        body = inner;
+                    }
+                    _ => return body,
+                }
+            }
+            Expr::Paren(expr) if expr.attrs.is_empty() => body = &expr.expr,
+            _ => return body,
+        }
+    }
+}
+
+/// Comma printed after the body of a match-like arm
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ArmComma {
+    /// Only where a match expression requires it, after bodies that are not blocks
+    Required,
+    Always,
+    Never,
+}
+
+/// Prints the body of a match-like arm after the `=>`. Blocks containing only an expression
+/// are unwrapped, and the body is wrapped in braces if it does not fit on the line.
+pub(crate) fn arm_body(printer: &mut Printer, body: &Expr) {
+    arm_body_with_comma(printer, body, ArmComma::Required);
+}
+
+/// Prints the body of a match-like arm like [`arm_body`], followed by the comma as given
+pub(crate) fn arm_body_with_comma(printer: &mut Printer, mut body: &Expr, comma: ArmComma) {
+    while let Expr::Block(expr) = body {
+        if expr.attrs.is_empty() && expr.label.is_none() {
+            let mut stmts = expr.block.stmts.iter();
+            if let (Some(Stmt::Expr(inner, None)), None) = (stmts.next(), stmts.next()) {
+                body = inner;
+                continue;
+            }
+        }
+        break;
+    }
+
+    if let Expr::Block(body) = body {
+        if let Some(label) = &body.label {
+            printer.label(label);
+        }
+        printer.word("{");
+        printer.neverbreak();
+        printer.cbox(INDENT);
+        printer.hardbreak_if_nonempty();
+        printer.inner_attrs(&body.attrs);
+        for stmt in body.block.stmts.iter().delimited() {
+            printer.stmt(&stmt, stmt.is_last);
+        }
+        printer.offset(-INDENT);
+        printer.end();
+        printer.word("}");
+        if comma == ArmComma::Always {
+            printer.word(",");
+        }
+    } else {
+        printer.neverbreak();
+        printer.cbox(INDENT);
+        let okay_to_brace = expr::parseable_as_stmt(body);
+        printer.scan_break(BreakToken {
+            pre_break: Some(if okay_to_brace { '{' } else { '(' }),
+            ..BreakToken::default()
+        });
+        printer.expr_beginning_of_line(body, false, true, FixupContext::new_match_arm());
+        printer.scan_break(BreakToken {
+            offset: -INDENT,
+            pre_break: (okay_to_brace && stmt::add_semi(body)).then_some(';'),
+            post_break: match (okay_to_brace, comma) {
+                (true, ArmComma::Always) => "},",
+                (true, _) => "}",
+                (false, ArmComma::Never) => ")",
+                (false, _) => "),",
+            },
+            no_break: match comma {
+                ArmComma::Required => requires_comma_to_be_match_arm(body).unwrap().then_some(','),
+                ArmComma::Always => Some(','),
+                ArmComma::Never => None,
+            },
+            ..BreakToken::default()
+        });
+        printer.end();
+    }
+}
+
+pub(crate) fn requires_comma_to_be_match_arm(expr: &Expr) -> syn::Result<bool> {
+    match expr {
+        Expr::If(_)
+        | Expr::Match(_)
+        | Expr::Block(_) | Expr::Unsafe(_) // both under ExprKind::Block in rustc
+        | Expr::While(_)
+        | Expr::Loop(_)
+        | Expr::ForLoop(_)
+        | Expr::TryBlock(_)
+        | Expr::Const(_) => Ok(false),
+
+        Expr::Array(_)
+        | Expr::Assign(_)
+        | Expr::Async(_)
+        | Expr::Await(_)
+        | Expr::Binary(_)
+        | Expr::Break(_)
+        | Expr::Call(_)
+        | Expr::Cast(_)
+        | Expr::Closure(_)
+        | Expr::Continue(_)
+        | Expr::Field(_)
+        | Expr::Group(_)
+        | Expr::Index(_)
+        | Expr::Infer(_)
+        | Expr::Let(_)
+        | Expr::Lit(_)
+        | Expr::Macro(_)
+        | Expr::MethodCall(_)
+        | Expr::Paren(_)
+        | Expr::Path(_)
+        | Expr::Range(_)
+        | Expr::RawAddr(_)
+        | Expr::Reference(_)
+        | Expr::Repeat(_)
+        | Expr::Return(_)
+        | Expr::Struct(_)
+        | Expr::Try(_)
+        | Expr::Tuple(_)
+        | Expr::Unary(_)
+        | Expr::Yield(_)
+        | Expr::Verbatim(_) => Ok(true),
+        _ => Err(syn::Error::new(expr.span(), "unhandled expression")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::macros::arm::normalized_arms;
+    use proc_macro2::TokenStream;
+    use std::str::FromStr;
+
+    fn normalized(arms: &str) -> String {
+        normalized_arms(TokenStream::from_str(arms).unwrap())
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_normalized_arms() {
+        assert_eq!(
+            normalized("a => { b() } c => ({ d::<E, F>() }), g => { return |h, i| h; }"),
+            normalized("a => b(), c => d::<E, F>(), g => return |h, i| h,"),
+        );
+        assert_ne!(normalized("a => { b(); }"), normalized("a => b(),"));
+        assert_ne!(
+            normalized("a => b(), c => d"),
+            normalized("a => b(), c => e")
+        );
+    }
+}
@@ -0,0 +1,244 @@
+use crate::config::Config;
+use crate::layout;
+use crate::macro_syntax::{MacroFactory, MacroSyntax};
+use crate::macros::{arm, shape};
+use prettyplease::algorithm::Printer;
+use prettyplease::fixup::FixupContext;
+use syn::parse::{Parse, ParseStream, Parser};
+use syn::{Expr, Macro, Pat, Token, parenthesized};
+
+/// `crossbeam_channel::select!`, with `recv(rx) -> msg`, `send(tx, value) -> res` and
+/// `default(timeout)` arms
+pub struct CrossbeamSelectFactory;
+
+impl MacroFactory for CrossbeamSelectFactory {
+    fn name() -> &'static str {
+        "select"
+    }
+
+    fn path() -> Option<&'static str> {
+        Some("crossbeam_channel::select")
+    }
+
+    fn parse(mac: &Macro) -> Option<Box<dyn MacroSyntax>> {
+        Some(Box::new(
+            Parser::parse2(SelectSyntax::parse, mac.tokens.clone()).ok()?,
+        ))
+    }
+}
+
+mod kw {
+    syn::custom_keyword!(recv);
+    syn::custom_keyword!(send);
+    syn::custom_keyword!(default);
+}
+
+struct SelectSyntax {
+    arms: Vec<ArmSyntax>,
+}
+
+struct ArmSyntax {
+    operation: Operation,
+    body: Expr,
+}
+
+enum Operation {
+    /// `recv(receiver) -> pat`
+    Recv { receiver: Expr, pat: Pat },
+    /// `send(sender, value) -> pat`
+    Send { sender: Expr, value: Expr, pat: Pat },
+    /// `default` or `default(timeout)`
+    Default { timeout: Option<Expr> },
+}
+
+impl Parse for Operation {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let lookahead = input.lookahead1();
+        if lookahead.peek(kw::recv) {
+            input.parse::<kw::recv>()?;
+            let content;
+            parenthesized!(content in input);
+            let receiver = content.parse()?;
+            expect_empty(&content)?;
+            Ok(Operation::Recv {
+                receiver,
+                pat: parse_result_pat(input)?,
+            })
+        } else if lookahead.peek(kw::send) {
+            input.parse::<kw::send>()?;
+            let content;
+            parenthesized!(content in input);
+            let sender = content.parse()?;
+            content.parse::<Token![,]>()?;
+            let value = content.parse()?;
+            expect_empty(&content)?;
+            Ok(Operation::Send {
+                sender,
+                value,
+                pat: parse_result_pat(input)?,
+            })
+        } else if lookahead.peek(kw::default) {
+            input.parse::<kw::default>()?;
+            let timeout = if input.peek(syn::token::Paren) {
+                let content;
+                parenthesized!(content in input);
+                let timeout = content.parse()?;
+                expect_empty(&content)?;
+                Some(timeout)
+            } else {
+                None
+            };
+            Ok(Operation::Default { timeout })
+        } else {
+            Err(lookahead.error())
+        }
+    }
+}
+
+fn parse_result_pat(input: ParseStream) -> syn::Result<Pat> {
+    input.parse::<Token![->]>()?;
+    Pat::parse_single(input)
+}
+
+fn expect_empty(content: ParseStream) -> syn::Result<()> {
+    if content.is_empty() {
+        Ok(())
+    } else {
+        Err(content.error("unexpected token"))
+    }
+}
+
+impl Parse for ArmSyntax {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let operation = input.parse()?;
+        input.parse::<Token![=>]>()?;
+        let (body, _) = arm::parse_arm_body(input)?;
+        Ok(ArmSyntax { operation, body })
+    }
+}
+
+impl Parse for SelectSyntax {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut arms = Vec::new();
+        while !input.is_empty() {
+            arms.push(input.parse()?);
+        }
+
+        Ok(Self { arms })
+    }
+}
+
+impl MacroSyntax for SelectSyntax {
+    fn delimiter_replacement(&self, base_indent: isize, config: &Config) -> String {
+        layout::print(config, base_indent, |printer, base_indent| {
+            select(printer, self, base_indent)
+        })
+    }
+
+    fn match_arms(&self) -> bool {
+        true
+    }
+}
+
+fn select(printer: &mut Printer, select_syntax: &SelectSyntax, base_indent: isize) {
+    printer.word("{");
+    shape::lines(
+        printer,
+        &select_syntax.arms,
+        base_indent,
+        |printer, arm_syntax, _| {
+            arm(printer, arm_syntax);
+            printer.hardbreak();
+        },
+    );
+    printer.word("}");
+}
+
+fn arm(printer: &mut Printer, arm_syntax: &ArmSyntax) {
+    printer.ibox(0);
+    match &arm_syntax.operation {
+        Operation::Recv { receiver, pat } => {
+            printer.word("recv(");
+            printer.expr(receiver, FixupContext::NONE);
+            printer.word(") -> ");
+            printer.pat(pat);
+        }
+        Operation::Send { sender, value, pat } => {
+            printer.word("send(");
+            printer.expr(sender, FixupContext::NONE);
+            printer.word(", ");
+            printer.expr(value, FixupContext::NONE);
+            printer.word(") -> ");
+            printer.pat(pat);
+        }
+        Operation::Default { timeout } => {
+            printer.word("default");
+            if let Some(timeout) = timeout {
+                printer.word("(");
+                printer.expr(timeout, FixupContext::NONE);
+                printer.word(")");
+            }
+        }
+    }
+    printer.word(" => ");
+    arm::arm_body(printer, &arm_syntax.body);
+    printer.end();
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{assert_eq_text, format_file};
+
+    #[test]
+    fn test_replace_crossbeam_select() {
+        let code = r#"
+use crossbeam_channel::select;
+
+fn func() {
+    loop {
+        select! {
+            recv( rx ) ->msg => { a.expr(msg) }
+            send(tx,value.clone())   ->   res => res.unwrap(),
+            recv(rx2) -> Ok(msg) => aaaaaaaaaaaaaaaaaaaaaaaa.expr().expr().expr().expr().expr().expr(),
+            default(Duration::from_millis(100)) => {
+                stmt();
+                break;
+            }
+        }
+        select! {
+            recv(rx) -> msg => a.expr(msg),
+            default => { break }
+        }
+    }
+}
+        "#;
+
+        let formatted = format_file(code);
+
+        let expected_formatted = r#"
+use crossbeam_channel::select;
+
+fn func() {
+    loop {
+        select! {
+            recv(rx) -> msg => a.expr(msg),
+            send(tx, value.clone()) -> res => res.unwrap(),
+            recv(rx2) -> Ok(msg) => {
+                aaaaaaaaaaaaaaaaaaaaaaaa.expr().expr().expr().expr().expr().expr()
+            }
+            default(Duration::from_millis(100)) => {
+                stmt();
+                break;
+            }
+        }
+        select! {
+            recv(rx) -> msg => a.expr(msg),
+            default => break,
+        }
+    }
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+}
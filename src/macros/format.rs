@@ -0,0 +1,341 @@
+use crate::config::Config;
+use crate::layout;
+use crate::macro_syntax::{MacroFormatter, MacroSyntax};
+use prettyplease::INDENT;
+use prettyplease::algorithm::Printer;
+use prettyplease::fixup::FixupContext;
+use syn::parse::{Parse, ParseStream, Parser};
+use syn::{Expr, Ident, Macro, MacroDelimiter, Token};
+
+/// Std formatting macros and the number of arguments before the format string
+pub(crate) const FORMAT_MACROS: [(&str, usize); 18] = [
+    ("format", 0),
+    ("format_args", 0),
+    ("print", 0),
+    ("println", 0),
+    ("eprint", 0),
+    ("eprintln", 0),
+    ("write", 1),
+    ("writeln", 1),
+    ("panic", 0),
+    ("unreachable", 0),
+    ("todo", 0),
+    ("unimplemented", 0),
+    ("assert", 1),
+    ("debug_assert", 1),
+    ("assert_eq", 2),
+    ("assert_ne", 2),
+    ("debug_assert_eq", 2),
+    ("debug_assert_ne", 2),
+];
+
+/// Formats invocations of a std formatting macro, like `println!`, `write!` or `assert_eq!`.
+/// The arguments are kept on one line if they fit, and otherwise broken one per line. A
+/// trailing comma is only kept when the arguments are broken.
+pub struct FormatFormatter {
+    name: &'static str,
+    path: String,
+    leading_args: usize,
+}
+
+impl FormatFormatter {
+    /// Formatter of the macro `std::<name>` with `leading_args` arguments before the format
+    /// string, e.g. 1 for the destination of `write!`
+    pub fn new(name: &'static str, leading_args: usize) -> Self {
+        Self {
+            name,
+            path: format!("std::{name}"),
+            leading_args,
+        }
+    }
+}
+
+impl MacroFormatter for FormatFormatter {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn path(&self) -> Option<&str> {
+        Some(&self.path)
+    }
+
+    fn parse(&self, mac: &Macro) -> Option<Box<dyn MacroSyntax>> {
+        let format_syntax =
+            (|input: ParseStream| FormatSyntax::parse(input, mac, self.leading_args))
+                .parse2(mac.tokens.clone())
+                .ok()?;
+        Some(Box::new(format_syntax))
+    }
+}
+
+struct FormatSyntax {
+    delimiter: MacroDelimiter,
+    /// Column of the opening delimiter
+    column: isize,
+    /// Arguments before the format string, e.g. the condition of `assert!`, the format string
+    /// and its arguments
+    args: Vec<FormatArg>,
+    trailing_comma: bool,
+}
+
+pub(crate) enum FormatArg {
+    Positional(Expr),
+    Named { name: Ident, value: Expr },
+}
+
+impl FormatSyntax {
+    fn parse(input: ParseStream, mac: &Macro, leading_args: usize) -> syn::Result<Self> {
+        let mut args: Vec<FormatArg> = Vec::new();
+        let mut trailing_comma = false;
+        while !input.is_empty() {
+            if args.len() <= leading_args {
+                // The leading arguments and the format string
+                args.push(FormatArg::Positional(input.parse()?));
+            } else {
+                let arg = input.parse()?;
+                if let (Some(FormatArg::Named { .. }), FormatArg::Positional(_)) =
+                    (args.last(), &arg)
+                {
+                    return Err(input.error("positional argument after named arguments"));
+                }
+                args.push(arg);
+            }
+
+            if input.is_empty() {
+                break;
+            }
+            input.parse::<Token![,]>()?;
+            trailing_comma = input.is_empty();
+        }
+        if args.len() < leading_args {
+            return Err(input.error("missing arguments"));
+        }
+
+        Ok(Self {
+            delimiter: mac.delimiter.clone(),
+            column: layout::delimiter_column(mac),
+            args,
+            trailing_comma,
+        })
+    }
+}
+
+impl Parse for FormatArg {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.peek(Ident) && input.peek2(Token![=]) && !input.peek2(Token![==]) {
+            let name = input.parse()?;
+            input.parse::<Token![=]>()?;
+            Ok(FormatArg::Named {
+                name,
+                value: input.parse()?,
+            })
+        } else {
+            Ok(FormatArg::Positional(input.parse()?))
+        }
+    }
+}
+
+impl MacroSyntax for FormatSyntax {
+    fn delimiter_replacement(&self, base_indent: isize, config: &Config) -> String {
+        layout::print_at(config, base_indent, self.column, |printer, base_indent| {
+            format_args(printer, self, base_indent)
+        })
+    }
+
+    fn optional_commas(&self) -> bool {
+        // The trailing comma is only printed if the arguments are broken
+        true
+    }
+}
+
+fn format_args(printer: &mut Printer, format_syntax: &FormatSyntax, base_indent: isize) {
+    list(
+        printer,
+        &format_syntax.delimiter,
+        &format_syntax.args,
+        format_syntax.trailing_comma,
+        base_indent,
+        format_arg,
+    );
+}
+
+pub(crate) fn format_arg(printer: &mut Printer, arg: &FormatArg) {
+    match arg {
+        FormatArg::Positional(expr) => printer.expr(expr, FixupContext::NONE),
+        FormatArg::Named { name, value } => {
+            printer.word(name.to_string());
+            printer.word(" = ");
+            printer.expr(value, FixupContext::NONE);
+        }
+    }
+}
+
+/// Comma separated arguments between the delimiters, kept on one line if they fit and otherwise
+/// broken one per line. The trailing comma is only printed if the arguments are broken.
+pub(crate) fn list<T>(
+    printer: &mut Printer,
+    delimiter: &MacroDelimiter,
+    elements: &[T],
+    trailing_comma: bool,
+    base_indent: isize,
+    print: impl Fn(&mut Printer, &T),
+) {
+    separated_list(
+        printer,
+        delimiter,
+        elements,
+        trailing_comma,
+        base_indent,
+        print,
+        |_| ",",
+    );
+}
+
+/// Like [`list`], with the separator after the element at each index given by `separator`,
+/// e.g. `;` after the key-values of a `log` macro
+pub(crate) fn separated_list<T>(
+    printer: &mut Printer,
+    delimiter: &MacroDelimiter,
+    elements: &[T],
+    trailing_comma: bool,
+    base_indent: isize,
+    print: impl Fn(&mut Printer, &T),
+    separator: impl Fn(usize) -> &'static str,
+) {
+    let (open, close) = match delimiter {
+        MacroDelimiter::Paren(_) => ("(", ")"),
+        MacroDelimiter::Brace(_) => ("{", "}"),
+        MacroDelimiter::Bracket(_) => ("[", "]"),
+    };
+
+    printer.word(open);
+    if !elements.is_empty() {
+        printer.cbox(INDENT + base_indent);
+        printer.zerobreak();
+        for (index, element) in elements.iter().enumerate() {
+            print(printer, element);
+            if index + 1 < elements.len() {
+                printer.word(separator(index));
+                printer.space();
+            } else if trailing_comma {
+                printer.trailing_comma(true);
+            } else {
+                printer.zerobreak();
+            }
+        }
+        printer.offset(-INDENT);
+        printer.end();
+    }
+    printer.word(close);
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Formatter, assert_eq_text};
+
+    #[test]
+    fn test_format_macros() {
+        let code = r#"
+fn func() {
+    println!("{} {}",a,b);
+    println!("{} {} {} {} {} {} {} {}", "test", "test", "test", "test", "test", "test", "test", "test");
+    write!(f, "{value} {}", aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa.expr(), value = bbbbbbbbbbbbbbbbbbbbbbbbbbb,)?;
+    assert_eq!(a, b, "{}", items.iter().map(|item| { let x = item.expr(); x + 1 }).count());
+    todo!();
+    std::panic!("test");
+    core::assert!(a  ==  b);
+}
+        "#;
+
+        let formatted = Formatter::default().format_str(code).unwrap();
+
+        let expected_formatted = r#"
+fn func() {
+    println!("{} {}", a, b);
+    println!(
+        "{} {} {} {} {} {} {} {}",
+        "test",
+        "test",
+        "test",
+        "test",
+        "test",
+        "test",
+        "test",
+        "test"
+    );
+    write!(
+        f,
+        "{value} {}",
+        aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa.expr(),
+        value = bbbbbbbbbbbbbbbbbbbbbbbbbbb,
+    )?;
+    assert_eq!(
+        a,
+        b,
+        "{}",
+        items
+            .iter()
+            .map(|item| {
+                let x = item.expr();
+                x + 1
+            })
+            .count()
+    );
+    todo!();
+    std::panic!("test");
+    core::assert!(a == b);
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+
+    #[test]
+    fn test_format_macros_after_code() {
+        let code = r#"
+fn func() {
+    let message = format!("{} {}", aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa.expr(), bbbbbbbbbbbbbbbbbbbbbb);
+    let message = format!("{} {}", aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa.expr(),bbbbbbbbbbbb);
+    items.push(
+        format!("{}", aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa.expr(bbbbbbbbbbbbb)),
+    );
+}
+        "#;
+
+        let formatted = Formatter::default().format_str(code).unwrap();
+
+        let expected_formatted = r#"
+fn func() {
+    let message = format!(
+        "{} {}",
+        aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa.expr(),
+        bbbbbbbbbbbbbbbbbbbbbb
+    );
+    let message = format!("{} {}", aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa.expr(), bbbbbbbbbbbb);
+    items.push(
+        format!(
+            "{}",
+            aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa.expr(bbbbbbbbbbbbb)
+        ),
+    );
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+
+    #[test]
+    fn test_format_macros_unparseable() {
+        let code = r#"
+fn func() {
+    println!("{a} {}", a = 1, 2);
+    assert_eq!(a);
+    write!(f "test");
+}
+        "#;
+
+        let formatted = Formatter::default().format_str(code).unwrap();
+
+        assert_eq_text!(formatted, code);
+    }
+}
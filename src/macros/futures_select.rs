@@ -0,0 +1,234 @@
+use crate::config::Config;
+use crate::layout;
+use crate::macro_syntax::{MacroFactory, MacroSyntax};
+use crate::macros::{arm, shape};
+use prettyplease::algorithm::Printer;
+use prettyplease::fixup::FixupContext;
+use syn::parse::{Parse, ParseStream, Parser};
+use syn::{Expr, Macro, Pat, Token};
+
+/// `futures::select!`, which unlike `tokio::select!` has `complete` and `default` arms
+pub struct FuturesSelectFactory;
+
+/// `futures::select_biased!`, with the same syntax as `futures::select!`
+pub struct FuturesSelectBiasedFactory;
+
+impl MacroFactory for FuturesSelectFactory {
+    fn name() -> &'static str {
+        "select"
+    }
+
+    fn path() -> Option<&'static str> {
+        Some("futures::select")
+    }
+
+    fn parse(mac: &Macro) -> Option<Box<dyn MacroSyntax>> {
+        parse_select(mac)
+    }
+}
+
+impl MacroFactory for FuturesSelectBiasedFactory {
+    fn name() -> &'static str {
+        "select_biased"
+    }
+
+    fn path() -> Option<&'static str> {
+        Some("futures::select_biased")
+    }
+
+    fn parse(mac: &Macro) -> Option<Box<dyn MacroSyntax>> {
+        parse_select(mac)
+    }
+}
+
+fn parse_select(mac: &Macro) -> Option<Box<dyn MacroSyntax>> {
+    Some(Box::new(
+        Parser::parse2(SelectSyntax::parse, mac.tokens.clone()).ok()?,
+    ))
+}
+
+mod kw {
+    syn::custom_keyword!(complete);
+    syn::custom_keyword!(default);
+}
+
+struct SelectSyntax {
+    arms: Vec<ArmSyntax>,
+}
+
+enum ArmSyntax {
+    /// `pat = future => body`
+    Future { pat: Pat, future: Expr, body: Expr },
+    /// `complete => body`, when all futures have completed
+    Complete(Expr),
+    /// `default => body`, when no future is ready
+    Default(Expr),
+}
+
+impl Parse for ArmSyntax {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.peek(kw::complete) && input.peek2(Token![=>]) {
+            input.parse::<kw::complete>()?;
+            input.parse::<Token![=>]>()?;
+            Ok(ArmSyntax::Complete(arm::parse_arm_body(input)?.0))
+        } else if input.peek(kw::default) && input.peek2(Token![=>]) {
+            input.parse::<kw::default>()?;
+            input.parse::<Token![=>]>()?;
+            Ok(ArmSyntax::Default(arm::parse_arm_body(input)?.0))
+        } else {
+            let pat = Pat::parse_multi_with_leading_vert(input)?;
+            input.parse::<Token![=]>()?;
+            let future = input.parse()?;
+            input.parse::<Token![=>]>()?;
+            let (body, _) = arm::parse_arm_body(input)?;
+            Ok(ArmSyntax::Future { pat, future, body })
+        }
+    }
+}
+
+impl Parse for SelectSyntax {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut arms = Vec::new();
+        while !input.is_empty() {
+            arms.push(input.parse()?);
+        }
+
+        Ok(Self { arms })
+    }
+}
+
+impl MacroSyntax for SelectSyntax {
+    fn delimiter_replacement(&self, base_indent: isize, config: &Config) -> String {
+        layout::print(config, base_indent, |printer, base_indent| {
+            select(printer, self, base_indent)
+        })
+    }
+
+    fn match_arms(&self) -> bool {
+        true
+    }
+}
+
+fn select(printer: &mut Printer, select_syntax: &SelectSyntax, base_indent: isize) {
+    printer.word("{");
+    shape::lines(
+        printer,
+        &select_syntax.arms,
+        base_indent,
+        |printer, arm_syntax, _| {
+            arm(printer, arm_syntax);
+            printer.hardbreak();
+        },
+    );
+    printer.word("}");
+}
+
+fn arm(printer: &mut Printer, arm_syntax: &ArmSyntax) {
+    printer.ibox(0);
+    let body = match arm_syntax {
+        ArmSyntax::Future { pat, future, body } => {
+            printer.pat(pat);
+            printer.word(" = ");
+            printer.expr(future, FixupContext::NONE);
+            body
+        }
+        ArmSyntax::Complete(body) => {
+            printer.word("complete");
+            body
+        }
+        ArmSyntax::Default(body) => {
+            printer.word("default");
+            body
+        }
+    };
+    printer.word(" => ");
+    arm::arm_body(printer, body);
+    printer.end();
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{assert_eq_text, format_file};
+
+    #[test]
+    fn test_replace_futures_select() {
+        let code = r#"
+use futures::{select, select_biased};
+
+fn func() {
+    loop {
+        select! {
+            val1 = future1 => { a.expr() }
+            Some(val2)   =   future2 => b.expr(),
+            default => {
+                stmt();
+                c.expr()
+            }
+            complete => { break }
+        }
+        select_biased! {
+            val1 = future1 => a.expr().expr().expr().expr().expr().expr().expr().expr().expr().expr(),
+            complete => break,
+        }
+    }
+}
+        "#;
+
+        let formatted = format_file(code);
+
+        let expected_formatted = r#"
+use futures::{select, select_biased};
+
+fn func() {
+    loop {
+        select! {
+            val1 = future1 => a.expr(),
+            Some(val2) = future2 => b.expr(),
+            default => {
+                stmt();
+                c.expr()
+            }
+            complete => break,
+        }
+        select_biased! {
+            val1 = future1 => {
+                a.expr().expr().expr().expr().expr().expr().expr().expr().expr().expr()
+            }
+            complete => break,
+        }
+    }
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+
+    #[test]
+    fn test_replace_futures_select_by_path() {
+        let code = r#"
+fn func() {
+    futures::select! {
+        complete   =>   { break }
+    }
+    tokio::select! {
+        complete   =>   { break }
+    }
+}
+        "#;
+
+        let formatted = format_file(code);
+
+        let expected_formatted = r#"
+fn func() {
+    futures::select! {
+        complete => break,
+    }
+    tokio::select! {
+        complete   =>   { break }
+    }
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+}
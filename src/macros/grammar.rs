@@ -0,0 +1,559 @@
+use crate::config::Config;
+use crate::layout;
+use crate::macro_syntax::{MacroFormatter, MacroSyntax};
+use crate::macros::arm::requires_comma_to_be_match_arm;
+use crate::macros::shape;
+use prettyplease::INDENT;
+use prettyplease::algorithm::Printer;
+use prettyplease::fixup::FixupContext;
+use prettyplease::iter::IterDelimited;
+use proc_macro2::{Ident, Spacing, TokenStream, TokenTree};
+use serde::Deserialize;
+use std::str::FromStr;
+use syn::ext::IdentExt;
+use syn::parse::{ParseStream, Parser};
+use syn::{Expr, ExprLit, ExprUnary, Lifetime, Lit, Macro, MacroDelimiter, Pat, Token, Type, UnOp};
+
+/// Grammar of a macro described by a pattern of `macro_rules!`-like fragments, configured in
+/// `plsfmt.toml` together with layout hints:
+///
+/// ```toml
+/// [grammars.my_select]
+/// pattern = "$pat:pat = $fut:expr => $body:expr,?"
+/// repeat = true
+/// one_per_line = true
+/// break_before = ["body"]
+/// ```
+///
+/// The pattern consists of metavariables `$name:fragment`, with the fragments `expr`, `pat`,
+/// `ty`, `ident`, `lifetime` and `literal`, and of literal punctuation and keywords. A literal
+/// followed by `?`, like `,?`, is optional. Whitespace in the pattern is printed as a single
+/// space. With `repeat` the invocation is a sequence of matches of the pattern, printed one per
+/// line with `one_per_line`. Metavariables in `break_before` are moved to the next line if they
+/// do not fit.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "GrammarConfig")]
+pub struct Grammar {
+    elements: Vec<Element>,
+    repeat: bool,
+    one_per_line: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GrammarConfig {
+    pattern: String,
+    #[serde(default)]
+    repeat: bool,
+    #[serde(default)]
+    one_per_line: bool,
+    #[serde(default)]
+    break_before: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Element {
+    space_before: bool,
+    kind: ElementKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ElementKind {
+    Literal {
+        text: String,
+        tokens: Vec<LiteralToken>,
+        optional: bool,
+    },
+    Metavariable {
+        name: String,
+        fragment: FragmentKind,
+        break_before: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum LiteralToken {
+    Punct(char, Spacing),
+    Ident(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FragmentKind {
+    Expr,
+    Pat,
+    Ty,
+    Ident,
+    Lifetime,
+    Literal,
+}
+
+impl TryFrom<GrammarConfig> for Grammar {
+    type Error = String;
+
+    fn try_from(config: GrammarConfig) -> Result<Self, Self::Error> {
+        let mut elements = Vec::new();
+        for (index, word) in config.pattern.split_whitespace().enumerate() {
+            let mut space_before = index > 0;
+            let mut rest = word;
+            while !rest.is_empty() {
+                let (kind, remaining) = match rest.strip_prefix('$') {
+                    Some(metavariable) => parse_metavariable(metavariable, &config.break_before)?,
+                    None => parse_literal(rest)?,
+                };
+                elements.push(Element { space_before, kind });
+                space_before = false;
+                rest = remaining;
+            }
+        }
+
+        let names: Vec<&str> = elements
+            .iter()
+            .filter_map(|element| match &element.kind {
+                ElementKind::Metavariable { name, .. } => Some(name.as_str()),
+                ElementKind::Literal { .. } => None,
+            })
+            .collect();
+        if names.is_empty() {
+            return Err(format!(
+                "pattern `{}` contains no metavariables",
+                config.pattern
+            ));
+        }
+        if let Some(name) = config
+            .break_before
+            .iter()
+            .find(|name| !names.contains(&name.as_str()))
+        {
+            return Err(format!("`{name}` in `break_before` is not in the pattern"));
+        }
+
+        Ok(Self {
+            elements,
+            repeat: config.repeat,
+            one_per_line: config.one_per_line,
+        })
+    }
+}
+
+/// Parses `name:fragment` at the start of `text`
+fn parse_metavariable<'a>(
+    text: &'a str,
+    break_before: &[String],
+) -> Result<(ElementKind, &'a str), String> {
+    let (name, rest) = split_ident(text);
+    let (fragment, rest) = rest
+        .strip_prefix(':')
+        .filter(|_| !name.is_empty())
+        .map(split_ident)
+        .ok_or_else(|| format!("expected `$name:fragment` at `${text}`"))?;
+    let fragment = match fragment {
+        "expr" => FragmentKind::Expr,
+        "pat" => FragmentKind::Pat,
+        "ty" => FragmentKind::Ty,
+        "ident" => FragmentKind::Ident,
+        "lifetime" => FragmentKind::Lifetime,
+        "literal" => FragmentKind::Literal,
+        _ => return Err(format!("unsupported fragment `{fragment}` in `${text}`")),
+    };
+
+    let kind = ElementKind::Metavariable {
+        name: name.to_string(),
+        fragment,
+        break_before: break_before.iter().any(|break_name| break_name == name),
+    };
+    Ok((kind, rest))
+}
+
+/// Parses the literal at the start of `text`, up to the next metavariable
+fn parse_literal(text: &str) -> Result<(ElementKind, &str), String> {
+    let (literal, rest) = text.split_at(text.find('$').unwrap_or(text.len()));
+    let (literal, optional) = match literal.strip_suffix('?') {
+        Some(literal) if !literal.is_empty() => (literal, true),
+        _ => (literal, false),
+    };
+
+    let unsupported = || format!("unsupported literal `{literal}`");
+    let tokens = TokenStream::from_str(literal)
+        .map_err(|_| unsupported())?
+        .into_iter()
+        .map(|token| match token {
+            TokenTree::Punct(punct) => Ok(LiteralToken::Punct(punct.as_char(), punct.spacing())),
+            TokenTree::Ident(ident) => Ok(LiteralToken::Ident(ident.to_string())),
+            TokenTree::Group(_) | TokenTree::Literal(_) => Err(unsupported()),
+        })
+        .collect::<Result<_, _>>()?;
+
+    let kind = ElementKind::Literal {
+        text: literal.to_string(),
+        tokens,
+        optional,
+    };
+    Ok((kind, rest))
+}
+
+fn split_ident(text: &str) -> (&str, &str) {
+    text.split_at(
+        text.find(|c: char| !c.is_alphanumeric() && c != '_')
+            .unwrap_or(text.len()),
+    )
+}
+
+/// Formats invocations of a macro according to a [`Grammar`]
+pub struct GrammarFormatter {
+    name: String,
+    grammar: Grammar,
+}
+
+impl GrammarFormatter {
+    pub fn new(name: impl Into<String>, grammar: Grammar) -> Self {
+        Self {
+            name: name.into(),
+            grammar,
+        }
+    }
+}
+
+impl MacroFormatter for GrammarFormatter {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn parse(&self, mac: &Macro) -> Option<Box<dyn MacroSyntax>> {
+        let repetitions = (|input: ParseStream| self.grammar.parse_repetitions(input))
+            .parse2(mac.tokens.clone())
+            .ok()?;
+
+        Some(Box::new(GrammarSyntax {
+            grammar: self.grammar.clone(),
+            delimiter: mac.delimiter.clone(),
+            column: layout::delimiter_column(mac),
+            repetitions,
+        }))
+    }
+}
+
+struct GrammarSyntax {
+    grammar: Grammar,
+    delimiter: MacroDelimiter,
+    /// Column of the opening delimiter
+    column: isize,
+    repetitions: Vec<Vec<Parsed>>,
+}
+
+/// Parsed element of the pattern
+enum Parsed {
+    /// Whether an optional literal is present
+    Literal(bool),
+    Fragment(Fragment),
+}
+
+enum Fragment {
+    Expr(Expr),
+    Pat(Pat),
+    Ty(Type),
+    Ident(Ident),
+    Lifetime(Lifetime),
+}
+
+impl MacroSyntax for GrammarSyntax {
+    fn delimiter_replacement(&self, base_indent: isize, config: &Config) -> String {
+        layout::print_at(config, base_indent, self.column, |printer, base_indent| {
+            grammar(printer, self, base_indent)
+        })
+    }
+}
+
+impl Grammar {
+    fn parse_repetitions(&self, input: ParseStream) -> syn::Result<Vec<Vec<Parsed>>> {
+        let mut repetitions = vec![self.parse_repetition(input)?];
+        while self.repeat && !input.is_empty() {
+            repetitions.push(self.parse_repetition(input)?);
+        }
+        Ok(repetitions)
+    }
+
+    fn parse_repetition(&self, input: ParseStream) -> syn::Result<Vec<Parsed>> {
+        self.elements
+            .iter()
+            .map(|element| match &element.kind {
+                ElementKind::Literal {
+                    text,
+                    tokens,
+                    optional,
+                } => {
+                    let present = !*optional || literal_matches(input, tokens);
+                    if present {
+                        parse_literal_tokens(input, text, tokens)?;
+                    }
+                    Ok(Parsed::Literal(present))
+                }
+                ElementKind::Metavariable { fragment, .. } => {
+                    let fragment = match fragment {
+                        FragmentKind::Expr => Fragment::Expr(input.parse()?),
+                        FragmentKind::Pat => {
+                            Fragment::Pat(Pat::parse_multi_with_leading_vert(input)?)
+                        }
+                        FragmentKind::Ty => Fragment::Ty(input.parse()?),
+                        FragmentKind::Ident => Fragment::Ident(input.call(Ident::parse_any)?),
+                        FragmentKind::Lifetime => Fragment::Lifetime(input.parse()?),
+                        FragmentKind::Literal => Fragment::Expr(parse_literal_fragment(input)?),
+                    };
+                    Ok(Parsed::Fragment(fragment))
+                }
+            })
+            .collect()
+    }
+}
+
+/// Literal with an optional leading `-`, like `-1`, as matched by `$l:literal`
+fn parse_literal_fragment(input: ParseStream) -> syn::Result<Expr> {
+    if !input.peek(Token![-]) {
+        return Ok(Expr::Lit(input.parse()?));
+    }
+    let minus = input.parse()?;
+    let literal: ExprLit = input.parse()?;
+    if !matches!(literal.lit, Lit::Int(_) | Lit::Float(_)) {
+        return Err(syn::Error::new(
+            literal.lit.span(),
+            "expected a numeric literal",
+        ));
+    }
+    Ok(Expr::Unary(ExprUnary {
+        attrs: Vec::new(),
+        op: UnOp::Neg(minus),
+        expr: Box::new(Expr::Lit(literal)),
+    }))
+}
+
+fn literal_matches(input: ParseStream, tokens: &[LiteralToken]) -> bool {
+    parse_literal_tokens(&input.fork(), "", tokens).is_ok()
+}
+
+fn parse_literal_tokens(
+    input: ParseStream,
+    text: &str,
+    tokens: &[LiteralToken],
+) -> syn::Result<()> {
+    for token in tokens {
+        input.step(|cursor| {
+            let rest = match token {
+                LiteralToken::Punct(ch, spacing) => cursor.punct().and_then(|(punct, rest)| {
+                    (punct.as_char() == *ch
+                        && (*spacing == Spacing::Alone || punct.spacing() == Spacing::Joint))
+                        .then_some(rest)
+                }),
+                LiteralToken::Ident(expected) => cursor
+                    .ident()
+                    .and_then(|(ident, rest)| (ident == expected).then_some(rest)),
+            };
+            rest.map(|rest| ((), rest))
+                .ok_or_else(|| cursor.error(format!("expected `{text}`")))
+        })?;
+    }
+    Ok(())
+}
+
+fn grammar(printer: &mut Printer, grammar_syntax: &GrammarSyntax, base_indent: isize) {
+    let (open, close, brace) = match grammar_syntax.delimiter {
+        MacroDelimiter::Paren(_) => ("(", ")", false),
+        MacroDelimiter::Brace(_) => ("{", "}", true),
+        MacroDelimiter::Bracket(_) => ("[", "]", false),
+    };
+    let elements = &grammar_syntax.grammar.elements;
+
+    printer.word(open);
+    if grammar_syntax.grammar.one_per_line {
+        shape::lines(
+            printer,
+            &grammar_syntax.repetitions,
+            base_indent,
+            |printer, parsed, _| {
+                repetition(printer, elements, parsed);
+                printer.hardbreak();
+            },
+        );
+    } else {
+        let outer_break = |printer: &mut Printer| {
+            if brace {
+                printer.space_if_nonempty();
+            } else {
+                printer.zerobreak();
+            }
+        };
+        printer.cbox(INDENT + base_indent);
+        outer_break(printer);
+        for parsed in grammar_syntax.repetitions.iter().delimited() {
+            repetition(printer, elements, &parsed);
+            if parsed.is_last {
+                outer_break(printer);
+            } else {
+                printer.space();
+            }
+        }
+        printer.offset(-INDENT);
+        printer.end();
+    }
+    printer.word(close);
+}
+
+fn repetition(printer: &mut Printer, elements: &[Element], parsed: &[Parsed]) {
+    printer.ibox(0);
+    for (element, parsed) in elements.iter().zip(parsed) {
+        match (&element.kind, parsed) {
+            (ElementKind::Literal { text, .. }, Parsed::Literal(true)) => {
+                if element.space_before {
+                    printer.word(" ");
+                }
+                printer.word(text.clone());
+            }
+            (ElementKind::Metavariable { break_before, .. }, Parsed::Fragment(fragment))
+                if *break_before && !is_blocklike(fragment) =>
+            {
+                printer.neverbreak();
+                printer.ibox(INDENT);
+                if element.space_before {
+                    printer.space();
+                } else {
+                    printer.zerobreak();
+                }
+                print_fragment(printer, fragment);
+                printer.end();
+            }
+            (ElementKind::Metavariable { .. }, Parsed::Fragment(fragment)) => {
+                if element.space_before {
+                    printer.word(" ");
+                }
+                print_fragment(printer, fragment);
+            }
+            (ElementKind::Literal { .. }, _) | (ElementKind::Metavariable { .. }, _) => {}
+        }
+    }
+    printer.end();
+}
+
+/// Expressions ending in a block are kept on the line even if they do not fit
+fn is_blocklike(fragment: &Fragment) -> bool {
+    matches!(fragment, Fragment::Expr(expr) if matches!(requires_comma_to_be_match_arm(expr), Ok(false)))
+}
+
+fn print_fragment(printer: &mut Printer, fragment: &Fragment) {
+    match fragment {
+        Fragment::Expr(expr) => printer.expr(expr, FixupContext::NONE),
+        Fragment::Pat(pat) => printer.pat(pat),
+        Fragment::Ty(ty) => printer.ty(ty),
+        Fragment::Ident(ident) => printer.word(ident.to_string()),
+        Fragment::Lifetime(lifetime) => printer.word(lifetime.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{ConfigFile, Formatter, assert_eq_text};
+
+    fn format_with_grammar(grammar: &str, code: &str) -> String {
+        let config_file = ConfigFile::parse(grammar).unwrap();
+        let formatter = Formatter::builder().config_file(&config_file).build();
+        formatter.format_str(code).unwrap()
+    }
+
+    #[test]
+    fn test_grammar_one_per_line() {
+        let grammar = r#"
+[grammars.my_select]
+pattern = "$pat:pat = $fut:expr => $body:expr,?"
+repeat = true
+one_per_line = true
+break_before = ["body"]
+"#;
+
+        let code = r#"
+fn func() {
+    my_select! { val1=future1.expr()=>a.expr(),
+        Some(val2)   =   future2.expr()  =>   { b.expr() }
+        val3 = future3.expr() => cccccccccccccccccccccccccccccc.expr(dddddddddddddddddddddddd)
+    }
+}
+        "#;
+
+        let formatted = format_with_grammar(grammar, code);
+
+        let expected_formatted = r#"
+fn func() {
+    my_select! {
+        val1 = future1.expr() => a.expr(),
+        Some(val2) = future2.expr() => { b.expr() }
+        val3 = future3.expr() =>
+            cccccccccccccccccccccccccccccc.expr(dddddddddddddddddddddddd)
+    }
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+
+    #[test]
+    fn test_grammar_inline() {
+        let grammar = r#"
+[grammars.route]
+pattern = "$method:ident $path:literal -> $handler:expr"
+"#;
+
+        let code = r#"
+fn func() {
+    route!(GET "/a"->handle_a);
+    route!(GET "/a" => handle_a);
+}
+        "#;
+
+        let formatted = format_with_grammar(grammar, code);
+
+        let expected_formatted = r#"
+fn func() {
+    route!(GET "/a" -> handle_a);
+    route!(GET "/a" => handle_a);
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+
+    #[test]
+    fn test_grammar_negative_literal() {
+        let grammar = r#"
+[grammars.range]
+pattern = "$name:ident = $min:literal .. $max:literal"
+"#;
+
+        let code = r#"
+fn func() {
+    range!(temperature=-40  ..  -1.5);
+    range!(name = -"a" .. 1);
+}
+        "#;
+
+        let formatted = format_with_grammar(grammar, code);
+
+        let expected_formatted = r#"
+fn func() {
+    range!(temperature = -40 .. -1.5);
+    range!(name = -"a" .. 1);
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+
+    #[test]
+    fn test_invalid_grammar() {
+        let invalid = [
+            r#"grammars.a.pattern = "$a:tt""#,
+            r#"grammars.a.pattern = "=>""#,
+            r#"grammars.a.pattern = "($a:expr)""#,
+            "grammars.a.pattern = \"$a:expr\"\ngrammars.a.break_before = [\"b\"]",
+        ];
+
+        for config in invalid {
+            assert!(ConfigFile::parse(config).is_err(), "{config}");
+        }
+    }
+}
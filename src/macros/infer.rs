@@ -0,0 +1,343 @@
+use crate::macros::shape::{MacroShape, Separators};
+use proc_macro2::{Delimiter, TokenStream, TokenTree};
+use std::collections::BTreeMap;
+use syn::ItemMacro;
+use syn::visit::{self, Visit};
+
+/// Shapes of macros inferred from their `macro_rules!` definitions. A shape is inferred if
+/// all rules of a macro have the same simple matcher shape, like `($($e:expr),* $(,)?)`.
+/// Macros defined more than once with different shapes get no shape.
+#[derive(Debug, Clone, Default)]
+pub struct InferredShapes {
+    shapes: BTreeMap<String, Option<(MacroShape, Separators)>>,
+}
+
+impl InferredShapes {
+    /// Infers shapes of the macros defined in `file`
+    pub fn add_file(&mut self, file: &syn::File) {
+        self.visit_file(file);
+    }
+
+    /// Inferred shape of each macro by name, and the separators its matcher accepts
+    pub fn shapes(&self) -> impl Iterator<Item = (&str, MacroShape, Separators)> {
+        self.shapes.iter().filter_map(|(name, shape)| {
+            let (shape, separators) = (*shape)?;
+            Some((name.as_str(), shape, separators))
+        })
+    }
+
+    fn add(&mut self, name: String, shape: Option<(MacroShape, Separators)>) {
+        self.shapes
+            .entry(name)
+            .and_modify(|existing| {
+                if *existing != shape {
+                    *existing = None;
+                }
+            })
+            .or_insert(shape);
+    }
+}
+
+impl<'ast> Visit<'ast> for InferredShapes {
+    fn visit_item_macro(&mut self, item: &'ast ItemMacro) {
+        if let (Some(ident), true) = (&item.ident, item.mac.path.is_ident("macro_rules")) {
+            self.add(ident.to_string(), infer_shape(item.mac.tokens.clone()));
+        }
+        visit::visit_item_macro(self, item);
+    }
+}
+
+/// Shape shared by all rules in the body of a `macro_rules!` definition. Empty matchers are
+/// compatible with any shape.
+fn infer_shape(rules: TokenStream) -> Option<(MacroShape, Separators)> {
+    let mut shape = None;
+    let mut tokens = rules.into_iter().peekable();
+    while tokens.peek().is_some() {
+        let Some(TokenTree::Group(matcher)) = tokens.next() else {
+            return None;
+        };
+        let (Some(TokenTree::Punct(eq)), Some(TokenTree::Punct(gt)), Some(TokenTree::Group(_))) =
+            (tokens.next(), tokens.next(), tokens.next())
+        else {
+            return None;
+        };
+        if eq.as_char() != '=' || gt.as_char() != '>' {
+            return None;
+        }
+        tokens.next_if(|token| matches!(token, TokenTree::Punct(punct) if punct.as_char() == ';'));
+
+        if matcher.stream().is_empty() {
+            continue;
+        }
+        let matcher_shape = matcher_shape(&normalize(matcher.stream()))?;
+        if shape.is_some_and(|shape| shape != matcher_shape) {
+            return None;
+        }
+        shape = Some(matcher_shape);
+    }
+    shape
+}
+
+/// Shape of a normalized matcher, see [`normalize`], and the separators it accepts
+fn matcher_shape(matcher: &str) -> Option<(MacroShape, Separators)> {
+    // No shape for statements, `$($s:stmt);*` takes the `;` as separator rather than as part of
+    // the statements printed by `MacroShape::Block`
+    const SEPARATED: &[(&str, &str, MacroShape)] = &[
+        ("$_:expr", ",", MacroShape::Args),
+        ("$_:ident:$_:expr", ",", MacroShape::Fields),
+        ("$_:pat=>$_:expr", ",", MacroShape::MatchArms),
+    ];
+
+    if matches!(matcher, "$($_:item)*" | "$($_:item)+") {
+        return Some((MacroShape::Items, Separators::Rustfmt));
+    }
+
+    SEPARATED.iter().find_map(|(element, separator, shape)| {
+        let repetitions = [
+            (format!("$({element}){separator}*"), Separators::Between),
+            (format!("$({element}){separator}+"), Separators::Between),
+            (
+                format!("$({element}){separator}*$({separator})?"),
+                Separators::Optional,
+            ),
+            (
+                format!("$({element}){separator}+$({separator})?"),
+                Separators::Optional,
+            ),
+            (format!("$({element}{separator})*"), Separators::Terminated),
+            (format!("$({element}{separator})+"), Separators::Terminated),
+        ];
+        repetitions
+            .into_iter()
+            .find(|(repetition, _)| repetition == matcher)
+            .map(|(_, separators)| (*shape, separators))
+    })
+}
+
+/// Matcher without whitespace and with metavariable names replaced by `_`, e.g.
+/// `$($e:expr),*` becomes `$($_:expr),*`
+fn normalize(matcher: TokenStream) -> String {
+    let mut normalized = String::new();
+    let mut after_dollar = false;
+    for token in matcher {
+        match &token {
+            TokenTree::Group(group) => {
+                let (open, close) = match group.delimiter() {
+                    Delimiter::Parenthesis => ("(", ")"),
+                    Delimiter::Brace => ("{", "}"),
+                    Delimiter::Bracket => ("[", "]"),
+                    Delimiter::None => ("", ""),
+                };
+                normalized.push_str(open);
+                normalized.push_str(&normalize(group.stream()));
+                normalized.push_str(close);
+            }
+            TokenTree::Ident(_) if after_dollar => normalized.push('_'),
+            token => normalized.push_str(&token.to_string()),
+        }
+        after_dollar = matches!(&token, TokenTree::Punct(punct) if punct.as_char() == '$');
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod test {
+    use crate::macros::infer::InferredShapes;
+    use crate::macros::shape::{MacroShape, Separators};
+    use crate::{Formatter, assert_eq_text};
+
+    fn inferred_shapes(code: &str) -> Vec<(String, MacroShape, Separators)> {
+        let mut shapes = InferredShapes::default();
+        shapes.add_file(&syn::parse_file(code).unwrap());
+        shapes
+            .shapes()
+            .map(|(name, shape, separators)| (name.to_string(), shape, separators))
+            .collect()
+    }
+
+    fn format_with_inferred_shapes(code: &str) -> String {
+        let mut shapes = InferredShapes::default();
+        shapes.add_file(&syn::parse_file(code).unwrap());
+        let mut formatter = Formatter::default();
+        formatter.register_inferred_shapes(&shapes);
+        formatter.format_str(code).unwrap()
+    }
+
+    #[test]
+    fn test_infer_shapes() {
+        let code = r#"
+macro_rules! my_args {
+    () => {};
+    ($($e:expr),+ $(,)?) => {};
+}
+
+macro_rules! my_items {
+    ($($item:item)*) => {};
+}
+
+macro_rules! my_fields {
+    ($($field:ident : $value:expr,)*) => {};
+}
+
+macro_rules! my_router {
+    ($($pat:pat => $body:expr),*) => {};
+}
+
+macro_rules! my_tokens {
+    ($($t:tt)*) => {};
+}
+
+macro_rules! my_stmts {
+    ($($s:stmt);*) => {};
+}
+
+macro_rules! mixed {
+    ($($e:expr),*) => {};
+    ($($item:item)*) => {};
+}
+        "#;
+
+        assert_eq!(
+            inferred_shapes(code),
+            [
+                (
+                    "my_args".to_string(),
+                    MacroShape::Args,
+                    Separators::Optional
+                ),
+                (
+                    "my_fields".to_string(),
+                    MacroShape::Fields,
+                    Separators::Terminated
+                ),
+                (
+                    "my_items".to_string(),
+                    MacroShape::Items,
+                    Separators::Rustfmt
+                ),
+                (
+                    "my_router".to_string(),
+                    MacroShape::MatchArms,
+                    Separators::Between
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_with_inferred_shapes() {
+        let code = r#"
+macro_rules! my_args {
+    ($($e:expr),* $(,)?) => {};
+}
+
+macro_rules! my_stmts {
+    ($($s:stmt);*) => {};
+}
+
+fn func() {
+    my_args!(a.expr(),b  +  c);
+    my_args!(a => b);
+    my_stmts! {let a=1; a}
+}
+        "#;
+
+        let formatted = format_with_inferred_shapes(code);
+
+        let expected_formatted = r#"
+macro_rules! my_args {
+    ($($e:expr),* $(,)?) => {};
+}
+
+macro_rules! my_stmts {
+    ($($s:stmt);*) => {};
+}
+
+fn func() {
+    my_args!(a.expr(), b + c);
+    my_args!(a => b);
+    my_stmts! {let a=1; a}
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+
+    #[test]
+    fn test_format_with_inferred_separators() {
+        let code = r#"
+macro_rules! my_list {
+    ($($e:expr),*) => {};
+}
+
+macro_rules! my_terminated {
+    ($($e:expr,)*) => {};
+}
+
+macro_rules! my_fields {
+    ($($field:ident : $value:expr,)*) => {};
+}
+
+macro_rules! my_router {
+    ($($pat:pat => $body:expr),*) => {};
+}
+
+fn func() {
+    my_list!(aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa, bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb, cccccccccccccccccccccc);
+    my_terminated!(a,b  +  c,);
+    my_terminated!(aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa, bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb, cccccccccccccccccccccc,);
+    my_fields! {a:1,b  :  c.expr(),}
+    my_router! {
+        "/a" => { handle_a() },
+        "/b" => { let b = 1; handle_b(b) },
+        _ => not_found()
+    }
+}
+        "#;
+
+        let formatted = format_with_inferred_shapes(code);
+
+        let expected_formatted = r#"
+macro_rules! my_list {
+    ($($e:expr),*) => {};
+}
+
+macro_rules! my_terminated {
+    ($($e:expr,)*) => {};
+}
+
+macro_rules! my_fields {
+    ($($field:ident : $value:expr,)*) => {};
+}
+
+macro_rules! my_router {
+    ($($pat:pat => $body:expr),*) => {};
+}
+
+fn func() {
+    my_list!(
+        aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa,
+        bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb,
+        cccccccccccccccccccccc
+    );
+    my_terminated!(a, b + c,);
+    my_terminated!(
+        aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa,
+        bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb,
+        cccccccccccccccccccccc,
+    );
+    my_fields! { a: 1, b: c.expr(), }
+    my_router! {
+        "/a" => handle_a(),
+        "/b" => {
+            let b = 1;
+            handle_b(b)
+        },
+        _ => not_found()
+    }
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+}
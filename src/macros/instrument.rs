@@ -0,0 +1,246 @@
+use crate::config::Config;
+use crate::layout;
+use crate::macro_syntax::{MacroFormatter, MacroSyntax};
+use crate::macros::format::{self, FormatArg};
+use crate::macros::tracing::{self, Field};
+use prettyplease::algorithm::Printer;
+use prettyplease::fixup::FixupContext;
+use syn::ext::IdentExt;
+use syn::parse::{Parse, ParseStream, Parser};
+use syn::token::Paren;
+use syn::{Expr, Ident, Macro, MacroDelimiter, Token, parenthesized};
+
+/// Formats the arguments of the `#[tracing::instrument(..)]` attribute, like `skip(a, b)`,
+/// `fields(x = %y)`, `err`, `ret` and `level = "debug"`. The arguments are kept on one line if
+/// they fit, and otherwise broken one per line, and so are the skip and field lists.
+pub struct InstrumentFormatter;
+
+impl MacroFormatter for InstrumentFormatter {
+    fn name(&self) -> &str {
+        "instrument"
+    }
+
+    fn path(&self) -> Option<&str> {
+        Some("tracing::instrument")
+    }
+
+    fn parse(&self, mac: &Macro) -> Option<Box<dyn MacroSyntax>> {
+        let instrument_syntax = (|input: ParseStream| InstrumentSyntax::parse(input, mac))
+            .parse2(mac.tokens.clone())
+            .ok()?;
+        Some(Box::new(instrument_syntax))
+    }
+}
+
+struct InstrumentSyntax {
+    delimiter: MacroDelimiter,
+    /// Column of the opening delimiter
+    column: isize,
+    args: List<InstrumentArg>,
+}
+
+enum InstrumentArg {
+    /// `skip(a, b)`
+    Skip(List<Ident>),
+    /// `fields(a = 1, ?b)`
+    Fields(List<Field>),
+    /// `key = value`, e.g. `level = "debug"` or `name = "span"`
+    Assign { key: Ident, value: Expr },
+    /// `key` or `key(args)`, e.g. `skip_all`, `err` or `ret(Debug, level = "info")`
+    Flag {
+        key: Ident,
+        args: Option<List<FormatArg>>,
+    },
+}
+
+/// Comma separated elements
+struct List<T> {
+    elements: Vec<T>,
+    trailing_comma: bool,
+}
+
+impl<T> List<T> {
+    fn parse_with(
+        input: ParseStream,
+        parse: fn(ParseStream) -> syn::Result<T>,
+    ) -> syn::Result<Self> {
+        let mut elements = Vec::new();
+        let mut trailing_comma = false;
+        while !input.is_empty() {
+            elements.push(parse(input)?);
+            if input.is_empty() {
+                break;
+            }
+            input.parse::<Token![,]>()?;
+            trailing_comma = input.is_empty();
+        }
+
+        Ok(Self {
+            elements,
+            trailing_comma,
+        })
+    }
+}
+
+impl InstrumentSyntax {
+    fn parse(input: ParseStream, mac: &Macro) -> syn::Result<Self> {
+        Ok(Self {
+            delimiter: mac.delimiter.clone(),
+            column: layout::delimiter_column(mac),
+            args: List::parse_with(input, InstrumentArg::parse)?,
+        })
+    }
+}
+
+impl Parse for InstrumentArg {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let key: Ident = input.parse()?;
+        if input.peek(Token![=]) {
+            input.parse::<Token![=]>()?;
+            return Ok(InstrumentArg::Assign {
+                key,
+                value: input.parse()?,
+            });
+        }
+        if !input.peek(Paren) {
+            return Ok(InstrumentArg::Flag { key, args: None });
+        }
+
+        let content;
+        parenthesized!(content in input);
+        if key == "skip" {
+            // Including `self`
+            Ok(InstrumentArg::Skip(List::parse_with(
+                &content,
+                Ident::parse_any,
+            )?))
+        } else if key == "fields" {
+            Ok(InstrumentArg::Fields(List::parse_with(
+                &content,
+                Field::parse,
+            )?))
+        } else {
+            Ok(InstrumentArg::Flag {
+                key,
+                args: Some(List::parse_with(&content, FormatArg::parse)?),
+            })
+        }
+    }
+}
+
+impl MacroSyntax for InstrumentSyntax {
+    fn delimiter_replacement(&self, base_indent: isize, config: &Config) -> String {
+        layout::print_at(config, base_indent, self.column, |printer, base_indent| {
+            format::list(
+                printer,
+                &self.delimiter,
+                &self.args.elements,
+                self.args.trailing_comma,
+                base_indent,
+                instrument_arg,
+            )
+        })
+    }
+
+    fn optional_commas(&self) -> bool {
+        true
+    }
+}
+
+fn instrument_arg(printer: &mut Printer, arg: &InstrumentArg) {
+    match arg {
+        InstrumentArg::Skip(idents) => {
+            printer.word("skip");
+            list(printer, idents, |printer, ident| {
+                printer.word(ident.to_string())
+            });
+        }
+        InstrumentArg::Fields(fields) => {
+            printer.word("fields");
+            list(printer, fields, tracing::field);
+        }
+        InstrumentArg::Assign { key, value } => {
+            printer.word(format!("{key} = "));
+            printer.expr(value, FixupContext::NONE);
+        }
+        InstrumentArg::Flag { key, args } => {
+            printer.word(key.to_string());
+            if let Some(args) = args {
+                list(printer, args, format::format_arg);
+            }
+        }
+    }
+}
+
+/// Parenthesized list nested in the arguments, broken one per line if it does not fit
+fn list<T>(printer: &mut Printer, list: &List<T>, print: impl Fn(&mut Printer, &T)) {
+    format::list(
+        printer,
+        &MacroDelimiter::Paren(Paren::default()),
+        &list.elements,
+        list.trailing_comma,
+        0,
+        print,
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{assert_eq_text, format_file};
+
+    #[test]
+    fn test_replace_instrument() {
+        let code = r#"
+use tracing::instrument;
+
+impl Service {
+    #[instrument(skip(self),err)]
+    async fn call(&self) {}
+
+    #[tracing::instrument(skip(self, request, connection_pool), fields(request.id = %request.id, user = ?user), err, ret(Debug), level = "debug")]
+    async fn handle(&self, request: Request, connection_pool: Pool) {}
+
+    #[instrument(fields(aaaaaaaaaaaaaaaaaaaaaaaa = %bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb, cccccccccccccccccc = ?ddddddddddddddddd))]
+    fn func() {}
+
+    #[cfg_attr(test ,  derive(Debug))]
+    #[doc = include_str!("doc.md")]
+    fn other() {}
+}
+        "#;
+
+        let formatted = format_file(code);
+
+        let expected_formatted = r#"
+use tracing::instrument;
+
+impl Service {
+    #[instrument(skip(self), err)]
+    async fn call(&self) {}
+
+    #[tracing::instrument(
+        skip(self, request, connection_pool),
+        fields(request.id = %request.id, user = ?user),
+        err,
+        ret(Debug),
+        level = "debug"
+    )]
+    async fn handle(&self, request: Request, connection_pool: Pool) {}
+
+    #[instrument(
+        fields(
+            aaaaaaaaaaaaaaaaaaaaaaaa = %bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb,
+            cccccccccccccccccc = ?ddddddddddddddddd
+        )
+    )]
+    fn func() {}
+
+    #[cfg_attr(test ,  derive(Debug))]
+    #[doc = include_str!("doc.md")]
+    fn other() {}
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+}
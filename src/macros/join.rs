@@ -0,0 +1,75 @@
+use crate::macro_syntax::{MacroFormatter, MacroSyntax};
+use crate::macros::shape::{self, MacroShape, Separators};
+use syn::Macro;
+
+/// Paths of the macros that join futures
+pub(crate) const JOIN_MACROS: [&str; 4] = [
+    "tokio::join",
+    "tokio::try_join",
+    "futures::join",
+    "futures::try_join",
+];
+
+/// Formats invocations of a macro that joins futures, like `tokio::join!`, as the arguments of
+/// a function call. The futures are broken one per line if they do not fit, and `async` block
+/// arguments are printed as indented blocks.
+pub struct JoinFormatter {
+    path: &'static str,
+}
+
+impl JoinFormatter {
+    pub fn new(path: &'static str) -> Self {
+        Self { path }
+    }
+}
+
+impl MacroFormatter for JoinFormatter {
+    fn name(&self) -> &str {
+        self.path.rsplit("::").next().unwrap_or(self.path)
+    }
+
+    fn path(&self) -> Option<&str> {
+        Some(self.path)
+    }
+
+    fn parse(&self, mac: &Macro) -> Option<Box<dyn MacroSyntax>> {
+        shape::parse_shape(mac, MacroShape::Args, Separators::Rustfmt)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{assert_eq_text, format_file};
+
+    #[test]
+    fn test_replace_join() {
+        let code = r#"
+async fn func() {
+    let (a, b) = tokio::join!(future1.expr(),future2.expr());
+    let (a, b) = futures::join!(async { let a = a.expr().await; a + 1 }, async move { b.expr().await });
+    let (a, b) = tokio::try_join!(aaaaaaaaaaaaaaaaaaaaaaaaaa.expr(), bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb.expr())?;
+}
+        "#;
+
+        let formatted = format_file(code);
+
+        let expected_formatted = r#"
+async fn func() {
+    let (a, b) = tokio::join!(future1.expr(), future2.expr());
+    let (a, b) = futures::join!(
+        async {
+            let a = a.expr().await;
+            a + 1
+        },
+        async move { b.expr().await },
+    );
+    let (a, b) = tokio::try_join!(
+        aaaaaaaaaaaaaaaaaaaaaaaaaa.expr(),
+        bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb.expr(),
+    )?;
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+}
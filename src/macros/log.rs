@@ -0,0 +1,311 @@
+use crate::config::Config;
+use crate::layout;
+use crate::macro_syntax::{MacroFormatter, MacroSyntax};
+use crate::macros::format::{self, FormatArg};
+use prettyplease::algorithm::Printer;
+use prettyplease::fixup::FixupContext;
+use proc_macro2::TokenTree;
+use syn::ext::IdentExt;
+use syn::parse::{ParseStream, Parser};
+use syn::{Expr, Ident, LitStr, Macro, MacroDelimiter, Token};
+
+/// Paths of the log crate macros, and whether the level is the first argument
+pub(crate) const LOG_MACROS: [(&str, bool); 6] = [
+    ("log::trace", false),
+    ("log::debug", false),
+    ("log::info", false),
+    ("log::warn", false),
+    ("log::error", false),
+    ("log::log", true),
+];
+
+/// Formats invocations of a log crate macro, like `log::info!`. The arguments are the `target:`
+/// and `logger:` prefixes, the level for `log!`, the key-values terminated by `;`, and the format
+/// string and its arguments. They are laid out like the arguments of the tracing macros.
+pub struct LogFormatter {
+    path: &'static str,
+    level: bool,
+}
+
+impl LogFormatter {
+    /// Formatter of the macro at `path`, with the level as the first argument after the
+    /// prefixes if `level`
+    pub fn new(path: &'static str, level: bool) -> Self {
+        Self { path, level }
+    }
+}
+
+impl MacroFormatter for LogFormatter {
+    fn name(&self) -> &str {
+        self.path.rsplit("::").next().unwrap_or(self.path)
+    }
+
+    fn path(&self) -> Option<&str> {
+        Some(self.path)
+    }
+
+    fn parse(&self, mac: &Macro) -> Option<Box<dyn MacroSyntax>> {
+        let log_syntax = (|input: ParseStream| LogSyntax::parse(input, mac, self.level))
+            .parse2(mac.tokens.clone())
+            .ok()?;
+        Some(Box::new(log_syntax))
+    }
+}
+
+struct LogSyntax {
+    delimiter: MacroDelimiter,
+    /// Column of the opening delimiter
+    column: isize,
+    args: Vec<LogArg>,
+    /// Index of the last key-value, which is followed by `;`
+    key_values_end: Option<usize>,
+    trailing_comma: bool,
+}
+
+enum LogArg {
+    /// `target: expr` or `logger: expr`
+    Prefix {
+        key: Ident,
+        value: Expr,
+    },
+    Level(Expr),
+    KeyValue(KeyValue),
+    Format(Expr),
+    /// Argument of the format string
+    FormatArg(FormatArg),
+}
+
+/// `key = value`, `key:capture = value` or the shorthand `key` and `key:capture`, where the
+/// capture is e.g. `?`, `%` or `serde`
+struct KeyValue {
+    /// Identifier or string literal
+    key: TokenTree,
+    capture: Option<TokenTree>,
+    value: Option<Expr>,
+}
+
+impl LogSyntax {
+    fn parse(input: ParseStream, mac: &Macro, level: bool) -> syn::Result<Self> {
+        // Groups are single token trees, so a `;` at the top level ends the key-values
+        let has_key_values = mac
+            .tokens
+            .clone()
+            .into_iter()
+            .any(|token| matches!(&token, TokenTree::Punct(punct) if punct.as_char() == ';'));
+
+        let mut args = Vec::new();
+        let mut key_values_end = None;
+        let mut trailing_comma = false;
+        while !input.is_empty() {
+            let prefixes_only = args.iter().all(|arg| matches!(arg, LogArg::Prefix { .. }));
+            let has_format = args.iter().any(|arg| matches!(arg, LogArg::Format(_)));
+            let arg = if prefixes_only && is_prefix(input) {
+                let key = input.parse()?;
+                input.parse::<Token![:]>()?;
+                LogArg::Prefix {
+                    key,
+                    value: input.parse()?,
+                }
+            } else if level && !args.iter().any(|arg| matches!(arg, LogArg::Level(_))) {
+                LogArg::Level(input.parse()?)
+            } else if has_key_values && key_values_end.is_none() {
+                LogArg::KeyValue(parse_key_value(input)?)
+            } else if !has_format {
+                LogArg::Format(input.parse()?)
+            } else {
+                let arg = input.parse()?;
+                if let (
+                    Some(LogArg::FormatArg(FormatArg::Named { .. })),
+                    FormatArg::Positional(_),
+                ) = (args.last(), &arg)
+                {
+                    return Err(input.error("positional argument after named arguments"));
+                }
+                LogArg::FormatArg(arg)
+            };
+            args.push(arg);
+
+            if input.is_empty() {
+                break;
+            }
+            if matches!(args.last(), Some(LogArg::KeyValue(_))) && input.peek(Token![;]) {
+                input.parse::<Token![;]>()?;
+                key_values_end = Some(args.len() - 1);
+                continue;
+            }
+            input.parse::<Token![,]>()?;
+            trailing_comma = input.is_empty();
+        }
+        if level && !args.iter().any(|arg| matches!(arg, LogArg::Level(_))) {
+            return Err(input.error("missing level"));
+        }
+        if !args.iter().any(|arg| matches!(arg, LogArg::Format(_))) {
+            return Err(input.error("missing format string"));
+        }
+
+        Ok(Self {
+            delimiter: mac.delimiter.clone(),
+            column: layout::delimiter_column(mac),
+            args,
+            key_values_end,
+            trailing_comma,
+        })
+    }
+}
+
+fn is_prefix(input: ParseStream) -> bool {
+    let fork = input.fork();
+    fork.parse::<Ident>().is_ok_and(|key| {
+        (key == "target" || key == "logger") && fork.peek(Token![:]) && !fork.peek(Token![::])
+    })
+}
+
+fn parse_key_value(input: ParseStream) -> syn::Result<KeyValue> {
+    let key = if input.peek(LitStr) {
+        TokenTree::Literal(input.parse::<LitStr>()?.token())
+    } else {
+        TokenTree::Ident(Ident::parse_any(input)?)
+    };
+    let capture = if input.peek(Token![:]) {
+        input.parse::<Token![:]>()?;
+        Some(input.parse()?)
+    } else {
+        None
+    };
+    let value = if input.peek(Token![=]) {
+        input.parse::<Token![=]>()?;
+        Some(input.parse()?)
+    } else {
+        None
+    };
+
+    Ok(KeyValue {
+        key,
+        capture,
+        value,
+    })
+}
+
+impl MacroSyntax for LogSyntax {
+    fn delimiter_replacement(&self, base_indent: isize, config: &Config) -> String {
+        layout::print_at(config, base_indent, self.column, |printer, base_indent| {
+            format::separated_list(
+                printer,
+                &self.delimiter,
+                &self.args,
+                self.trailing_comma,
+                base_indent,
+                log_arg,
+                |index| {
+                    if Some(index) == self.key_values_end {
+                        ";"
+                    } else {
+                        ","
+                    }
+                },
+            )
+        })
+    }
+
+    fn optional_commas(&self) -> bool {
+        true
+    }
+}
+
+fn log_arg(printer: &mut Printer, arg: &LogArg) {
+    match arg {
+        LogArg::Prefix { key, value } => {
+            printer.word(format!("{key}: "));
+            printer.expr(value, FixupContext::NONE);
+        }
+        LogArg::Level(expr) | LogArg::Format(expr) => printer.expr(expr, FixupContext::NONE),
+        LogArg::KeyValue(key_value) => {
+            printer.word(key_value.key.to_string());
+            if let Some(capture) = &key_value.capture {
+                printer.word(format!(":{capture}"));
+            }
+            if let Some(value) = &key_value.value {
+                printer.word(" = ");
+                printer.expr(value, FixupContext::NONE);
+            }
+        }
+        LogArg::FormatArg(arg) => format::format_arg(printer, arg),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{assert_eq_text, format_file};
+
+    #[test]
+    fn test_replace_log() {
+        let code = r#"
+use log::{info, Level};
+
+fn func() {
+    info!(target: "app",user_id = id,request:? = request;"handled {}",path);
+    log::log!(Level::Warn,"message");
+    log::error!(target: "app", aaaaaaaaaaaaaaaaaaaaaaa:% = bbbbbbbbbbbbbbbbbbb, ccccccccccccc; "message {} {value}", x, value = y);
+    log::debug!(elapsed:serde;"done");
+}
+        "#;
+
+        let formatted = format_file(code);
+
+        let expected_formatted = r#"
+use log::{info, Level};
+
+fn func() {
+    info!(target: "app", user_id = id, request:? = request; "handled {}", path);
+    log::log!(Level::Warn, "message");
+    log::error!(
+        target: "app",
+        aaaaaaaaaaaaaaaaaaaaaaa:% = bbbbbbbbbbbbbbbbbbb,
+        ccccccccccccc;
+        "message {} {value}",
+        x,
+        value = y
+    );
+    log::debug!(elapsed:serde; "done");
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+
+    #[test]
+    fn test_replace_log_unparseable() {
+        let code = r#"
+fn func() {
+    log::info!(target:  "app");
+    log::log!( "missing level" );
+    log::info!(a = 1;  b = 2; "message");
+}
+        "#;
+
+        let formatted = format_file(code);
+
+        assert_eq_text!(formatted, code);
+    }
+
+    #[test]
+    fn test_replace_unresolved_log() {
+        let code = r#"
+fn func() {
+    info!(a = 1;"message");
+    info!(a = 1,"message");
+}
+        "#;
+
+        let formatted = format_file(code);
+
+        let expected_formatted = r#"
+fn func() {
+    info!(a = 1; "message");
+    info!(a = 1, "message");
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+}
@@ -1,3 +1,15 @@
+pub(crate) mod arm;
+pub mod crossbeam_select;
+pub mod format;
+pub mod futures_select;
+pub mod grammar;
+pub mod infer;
+pub mod instrument;
+pub mod join;
+pub mod log;
+pub mod plugin;
 pub mod select;
+pub mod shape;
 #[cfg(test)]
 mod test_helper;
+pub mod tracing;
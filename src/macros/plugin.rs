@@ -0,0 +1,408 @@
+use crate::config::Config;
+use crate::layout;
+use crate::macro_syntax::{MacroFormatter, MacroSyntax};
+use crate::model::SkipReason;
+use crate::report::path_to_string;
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use syn::{Macro, MacroDelimiter};
+
+/// External formatter of a macro, configured in `plsfmt.toml` by macro name and command:
+///
+/// ```toml
+/// [plugins.my_macro]
+/// command = ["my-macro-fmt", "--plugin"]
+/// ```
+///
+/// The command is started on the first invocation of the macro and kept running. For each
+/// invocation plsfmt writes a request as one line of JSON to its stdin, shown wrapped here:
+///
+/// ```json
+/// {"version":1,"path":"my_macro","delimiter":"paren","tokens":"a , b",
+///  "base_indent":4,"column":12,"max_width":89,"tab_spaces":4}
+/// ```
+///
+/// `version` is the [`PROTOCOL_VERSION`] of the request. `base_indent` is the indentation of the
+/// line of the invocation and `column` the column of the opening delimiter, with tabs counted as
+/// one column and `max_width` reduced by the remaining width of the tabs in the indentation.
+/// plsfmt reads the response as one line of JSON from the stdout of the plugin, either
+/// `{"replacement":"(a, b)"}`, the formatted delimiters and tokens between them, with lines
+/// after the first indented to absolute columns like [`MacroSyntax::delimiter_replacement`],
+/// or `"skip"` to leave the invocation unformatted. Replacements that change the tokens are not
+/// applied. If the plugin cannot be started or fails, the remaining invocations of the macro
+/// are skipped and reported as failed, as are those of a plugin that does not respond within 5
+/// seconds, which is killed. The plugin is expected to exit when its stdin is closed, and is
+/// killed otherwise.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Plugin {
+    /// Program and its arguments
+    pub command: Vec<String>,
+}
+
+/// Formatter that sends the invocations of a macro to a [`Plugin`]
+pub struct PluginFormatter {
+    name: String,
+    process: Arc<Mutex<PluginProcess>>,
+}
+
+enum PluginProcess {
+    NotStarted(Vec<String>),
+    Running {
+        /// With the piped stdin, which is closed when the process is dropped
+        child: Child,
+        /// Lines of the stdout of the process, read on a separate thread such that waiting for
+        /// a response can time out
+        responses: Receiver<io::Result<String>>,
+    },
+    /// With the error message
+    Failed(String),
+}
+
+/// Time a plugin has to exit after its stdin is closed before it is killed
+const EXIT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Time a plugin has to respond to a request before it is killed
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Version of the plugin requests, incremented on incompatible changes
+pub const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct Request<'a> {
+    version: u32,
+    path: &'a str,
+    delimiter: &'static str,
+    tokens: &'a str,
+    base_indent: isize,
+    column: isize,
+    max_width: usize,
+    tab_spaces: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Response {
+    Replacement(String),
+    Skip,
+}
+
+impl PluginFormatter {
+    pub fn new(name: impl Into<String>, plugin: &Plugin) -> Self {
+        Self {
+            name: name.into(),
+            process: Arc::new(Mutex::new(PluginProcess::NotStarted(
+                plugin.command.clone(),
+            ))),
+        }
+    }
+}
+
+impl MacroFormatter for PluginFormatter {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn parse(&self, mac: &Macro) -> Option<Box<dyn MacroSyntax>> {
+        Some(Box::new(PluginSyntax {
+            process: self.process.clone(),
+            path: path_to_string(&mac.path),
+            delimiter: mac.delimiter.clone(),
+            column: layout::delimiter_column(mac),
+            tokens: mac.tokens.to_string(),
+        }))
+    }
+}
+
+struct PluginSyntax {
+    process: Arc<Mutex<PluginProcess>>,
+    path: String,
+    delimiter: MacroDelimiter,
+    column: isize,
+    tokens: String,
+}
+
+impl MacroSyntax for PluginSyntax {
+    fn delimiter_replacement(&self, base_indent: isize, config: &Config) -> String {
+        self.try_delimiter_replacement(base_indent, config)
+            .unwrap_or_else(|_| {
+                let (open, close) = delimiters(&self.delimiter);
+                format!("{open}{}{close}", self.tokens)
+            })
+    }
+
+    fn try_delimiter_replacement(
+        &self,
+        base_indent: isize,
+        config: &Config,
+    ) -> Result<String, SkipReason> {
+        let request = Request {
+            version: PROTOCOL_VERSION,
+            path: &self.path,
+            delimiter: match self.delimiter {
+                MacroDelimiter::Paren(_) => "paren",
+                MacroDelimiter::Brace(_) => "brace",
+                MacroDelimiter::Bracket(_) => "bracket",
+            },
+            tokens: &self.tokens,
+            base_indent,
+            column: self.column,
+            max_width: config.max_width,
+            tab_spaces: config.tab_spaces,
+        };
+
+        let mut process = self.process.lock().unwrap();
+        if let PluginProcess::Failed(message) = &*process {
+            return Err(SkipReason::Failed(message.clone()));
+        }
+        match process.request(&request) {
+            Ok(Response::Replacement(replacement)) => Ok(replacement),
+            Ok(Response::Skip) => Err(SkipReason::Declined),
+            Err(err) => {
+                let message = format!("plugin for `{}!` failed: {err}", self.path);
+                *process = PluginProcess::Failed(message.clone());
+                Err(SkipReason::Failed(message))
+            }
+        }
+    }
+}
+
+impl PluginProcess {
+    fn request(&mut self, request: &Request) -> io::Result<Response> {
+        if let PluginProcess::NotStarted(command) = self {
+            *self = Self::start(command)?;
+        }
+        let PluginProcess::Running { child, responses } = self else {
+            unreachable!("plugin is running");
+        };
+        let stdin = child.stdin.as_mut().expect("stdin is piped");
+
+        let mut line = serde_json::to_string(request)?;
+        line.push('\n');
+        stdin.write_all(line.as_bytes())?;
+        stdin.flush()?;
+
+        match responses.recv_timeout(RESPONSE_TIMEOUT) {
+            Ok(line) => Ok(serde_json::from_str(&line?)?),
+            Err(RecvTimeoutError::Timeout) => {
+                let _ = child.kill();
+                Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("no response within {}s", RESPONSE_TIMEOUT.as_secs()),
+                ))
+            }
+            Err(RecvTimeoutError::Disconnected) => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "plugin exited",
+            )),
+        }
+    }
+
+    fn start(command: &[String]) -> io::Result<Self> {
+        let Some((program, args)) = command.split_first() else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "empty plugin command",
+            ));
+        };
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let (sender, responses) = mpsc::channel();
+        thread::spawn(move || {
+            for line in stdout.lines() {
+                let error = line.is_err();
+                if sender.send(line).is_err() || error {
+                    break;
+                }
+            }
+        });
+        Ok(PluginProcess::Running { child, responses })
+    }
+}
+
+impl Drop for PluginProcess {
+    fn drop(&mut self) {
+        let PluginProcess::Running { child, .. } = self else {
+            return;
+        };
+        drop(child.stdin.take());
+        let deadline = Instant::now() + EXIT_TIMEOUT;
+        while matches!(child.try_wait(), Ok(None)) {
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = child.wait();
+    }
+}
+
+fn delimiters(delimiter: &MacroDelimiter) -> (&'static str, &'static str) {
+    match delimiter {
+        MacroDelimiter::Paren(_) => ("(", ")"),
+        MacroDelimiter::Brace(_) => ("{", "}"),
+        MacroDelimiter::Bracket(_) => ("[", "]"),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use crate::model::SkipReason;
+    use crate::{ConfigFile, Formatter, assert_eq_text};
+    use std::{fs, process};
+
+    #[test]
+    fn test_plugins() {
+        let config_file = r#"
+[plugins.ok_macro]
+command = ["sh", "-c", "while read request; do echo '{\"replacement\":\"(a, b)\"}'; done"]
+
+[plugins.changed_macro]
+command = ["sh", "-c", "while read request; do echo '{\"replacement\":\"(a, c)\"}'; done"]
+
+[plugins.skip_macro]
+command = ["sh", "-c", "while read request; do echo '\"skip\"'; done"]
+
+[plugins.missing_macro]
+command = ["plsfmt-missing-plugin"]
+"#;
+        let formatter = Formatter::builder()
+            .config_file(&ConfigFile::parse(config_file).unwrap())
+            .build();
+
+        let code = r#"
+fn func() {
+    ok_macro!(a ,b);
+    ok_macro!(a ,b ,);
+    changed_macro!(a ,b);
+    skip_macro!(a ,b);
+    missing_macro!(a ,b);
+    missing_macro!(a ,b);
+}
+"#;
+
+        let (formatted, report) = formatter.format_str_with_report(code).unwrap();
+
+        assert_eq_text!(
+            formatted,
+            r#"
+fn func() {
+    ok_macro!(a, b);
+    ok_macro!(a ,b ,);
+    changed_macro!(a ,b);
+    skip_macro!(a ,b);
+    missing_macro!(a ,b);
+    missing_macro!(a ,b);
+}
+"#
+        );
+        let skipped: Vec<_> = report
+            .macros
+            .iter()
+            .map(|mac| mac.skipped.clone())
+            .collect();
+        assert_eq!(
+            skipped[..4],
+            [
+                None,
+                Some(SkipReason::TokensChanged),
+                Some(SkipReason::TokensChanged),
+                Some(SkipReason::Declined),
+            ]
+        );
+        for skipped in &skipped[4..] {
+            assert!(
+                matches!(skipped, Some(SkipReason::Failed(message))
+                    if message.starts_with("plugin for `missing_macro!` failed: ")),
+                "{skipped:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_plugin_request() {
+        let requests =
+            std::env::temp_dir().join(format!("plsfmt-plugin-requests-{}", process::id()));
+        let config_file = format!(
+            r#"
+[plugins.my_macro]
+command = ["sh", "-c", "while read request; do echo \"$request\" >> {}; echo '\"skip\"'; done"]
+"#,
+            requests.display()
+        );
+        let formatter = Formatter::builder()
+            .config_file(&ConfigFile::parse(&config_file).unwrap())
+            .build();
+
+        formatter
+            .format_str("fn func() {\n    let a = my_macro!(a ,b);\n}\n")
+            .unwrap();
+        drop(formatter);
+
+        assert_eq!(
+            fs::read_to_string(&requests).unwrap(),
+            concat!(
+                r#"{"version":1,"path":"my_macro","delimiter":"paren","tokens":"a , b","#,
+                r#""base_indent":4,"column":21,"max_width":89,"tab_spaces":4}"#,
+                "\n"
+            )
+        );
+        fs::remove_file(requests).unwrap();
+    }
+
+    #[test]
+    fn test_plugin_exits_on_drop() {
+        let exited = std::env::temp_dir().join(format!("plsfmt-plugin-exited-{}", process::id()));
+        let config_file = format!(
+            r#"
+[plugins.ok_macro]
+command = ["sh", "-c", "while read request; do echo '\"skip\"'; done; touch {}"]
+"#,
+            exited.display()
+        );
+        let formatter = Formatter::builder()
+            .config_file(&ConfigFile::parse(&config_file).unwrap())
+            .build();
+        formatter.format_str("fn func() { ok_macro!(a); }").unwrap();
+
+        drop(formatter);
+
+        assert!(exited.exists());
+        fs::remove_file(exited).unwrap();
+    }
+
+    #[test]
+    fn test_plugin_timeout() {
+        let config_file = r#"
+[plugins.hanging_macro]
+command = ["sh", "-c", "read request; exec sleep 60"]
+"#;
+        let formatter = Formatter::builder()
+            .config_file(&ConfigFile::parse(config_file).unwrap())
+            .build();
+
+        let code = "fn func() {\n    hanging_macro!(a ,b);\n    hanging_macro!(a ,b);\n}\n";
+        let (formatted, report) = formatter.format_str_with_report(code).unwrap();
+
+        assert_eq_text!(formatted, code);
+        assert_eq!(report.macros.len(), 2);
+        for mac in &report.macros {
+            assert_eq!(
+                mac.skipped,
+                Some(SkipReason::Failed(
+                    "plugin for `hanging_macro!` failed: no response within 5s".to_string()
+                ))
+            );
+        }
+    }
+}
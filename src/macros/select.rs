@@ -1,12 +1,13 @@
+use crate::config::Config;
+use crate::layout;
 use crate::macro_syntax::{MacroFactory, MacroSyntax};
-use prettyplease::algorithm::{BreakToken, Printer};
+use crate::macros::arm;
+use prettyplease::INDENT;
+use prettyplease::algorithm::Printer;
 use prettyplease::fixup::FixupContext;
-use prettyplease::iter::IterDelimited;
-use prettyplease::{INDENT, expr, stmt};
 use syn::parse::{Parse, ParseStream, Parser};
-use syn::spanned::Spanned;
 use syn::token::Comma;
-use syn::{Expr, Macro, Pat, Stmt, Token};
+use syn::{Expr, Macro, Pat, Token};
 
 pub struct SelectFactory;
 
@@ -15,6 +16,10 @@ impl MacroFactory for SelectFactory {
         "select"
     }
 
+    fn path() -> Option<&'static str> {
+        Some("tokio::select")
+    }
+
     fn parse(mac: &Macro) -> Option<Box<dyn MacroSyntax>> {
         Some(Box::new(
             Parser::parse2(SelectSyntax::parse, mac.tokens.clone()).ok()?,
@@ -23,16 +28,27 @@ impl MacroFactory for SelectFactory {
 }
 
 impl MacroSyntax for SelectSyntax {
-    fn delimiter_replacement(&self, base_indent: isize) -> String {
-        let mut printer = prettyplease::algorithm::Printer::new();
-        // let base_indent = mac.path.span().start().column as isize;
-        select(&mut printer, self, base_indent);
-        printer.eof()
+    fn delimiter_replacement(&self, base_indent: isize, config: &Config) -> String {
+        layout::print(config, base_indent, |printer, base_indent| {
+            select(printer, self, base_indent)
+        })
     }
+
+    fn match_arms(&self) -> bool {
+        true
+    }
+}
+
+mod kw {
+    syn::custom_keyword!(biased);
 }
 
 struct SelectSyntax {
+    /// `biased;` before the arms, polling the futures in order
+    biased: bool,
     arms: Vec<ArmSyntax>,
+    /// Body of the `else => body` branch after the arms
+    else_body: Option<Expr>,
 }
 
 struct ArmSyntax {
@@ -40,6 +56,8 @@ struct ArmSyntax {
     #[allow(unused)]
     eq: Token![=],
     future: Expr,
+    /// Condition of `, if condition` after the future
+    precondition: Option<Expr>,
     #[allow(unused)]
     fat_arrow: Token![=>],
     body: Expr,
@@ -49,38 +67,59 @@ struct ArmSyntax {
 
 impl Parse for ArmSyntax {
     fn parse(input: ParseStream) -> syn::Result<Self> {
-        let requires_comma;
-        let arm = ArmSyntax {
-            pat: Pat::parse_multi_with_leading_vert(input)?,
-            eq: input.parse()?,
-            future: input.parse()?,
-            fat_arrow: input.parse()?,
-            body: {
-                let body = Expr::parse_with_earlier_boundary_rule(input)?;
-                requires_comma = requires_comma_to_be_match_arm(&body)?;
-                body
-            },
-            comma: {
-                if requires_comma && !input.is_empty() {
-                    Some(input.parse()?)
-                } else {
-                    input.parse()?
-                }
-            },
+        let pat = Pat::parse_multi_with_leading_vert(input)?;
+        let eq = input.parse()?;
+        let future = input.parse()?;
+        let precondition = if input.peek(Token![,]) && input.peek2(Token![if]) {
+            input.parse::<Token![,]>()?;
+            input.parse::<Token![if]>()?;
+            Some(input.parse()?)
+        } else {
+            None
         };
-
-        Ok(arm)
+        let fat_arrow = input.parse()?;
+        let (body, comma) = arm::parse_arm_body(input)?;
+
+        Ok(ArmSyntax {
+            pat,
+            eq,
+            future,
+            precondition,
+            fat_arrow,
+            body,
+            comma,
+        })
     }
 }
 
 impl Parse for SelectSyntax {
     fn parse(input: ParseStream) -> syn::Result<Self> {
+        let biased = input.peek(kw::biased) && input.peek2(Token![;]);
+        if biased {
+            input.parse::<kw::biased>()?;
+            input.parse::<Token![;]>()?;
+        }
+
         let mut arms = Vec::new();
+        let mut else_body = None;
         while !input.is_empty() {
-            arms.push(input.parse()?);
+            if input.peek(Token![else]) {
+                input.parse::<Token![else]>()?;
+                input.parse::<Token![=>]>()?;
+                else_body = Some(arm::parse_arm_body(input)?.0);
+                if !input.is_empty() {
+                    return Err(input.error("the else branch must be the last branch"));
+                }
+            } else {
+                arms.push(input.parse()?);
+            }
         }
 
-        Ok(Self { arms })
+        Ok(Self {
+            biased,
+            arms,
+            else_body,
+        })
     }
 }
 
@@ -89,10 +128,21 @@ fn select(printer: &mut Printer, select_syntax: &SelectSyntax, base_indent: isiz
     printer.neverbreak();
     printer.cbox(INDENT + base_indent);
     printer.hardbreak_if_nonempty();
+    if select_syntax.biased {
+        printer.word("biased;");
+        printer.hardbreak();
+    }
     for arm_syntax in &select_syntax.arms {
         arm(printer, arm_syntax);
         printer.hardbreak();
     }
+    if let Some(else_body) = &select_syntax.else_body {
+        printer.ibox(0);
+        printer.word("else => ");
+        arm::arm_body(printer, else_body);
+        printer.end();
+        printer.hardbreak();
+    }
     printer.offset(-INDENT);
     printer.end();
     printer.word("}");
@@ -103,103 +153,16 @@ fn arm(printer: &mut Printer, arm_syntax: &ArmSyntax) {
     printer.pat(&arm_syntax.pat);
     printer.word(" = ");
     printer.expr(&arm_syntax.future, FixupContext::NONE);
-    printer.word(" => ");
-
-    let mut body = &arm_syntax.body;
-    while let Expr::Block(expr) = body {
-        if expr.attrs.is_empty() && expr.label.is_none() {
-            let mut stmts = expr.block.stmts.iter();
-            if let (Some(Stmt::Expr(inner, None)), None) = (stmts.next(), stmts.next()) {
-                body = inner;
-                continue;
-            }
-        }
-        break;
-    }
-
-    if let Expr::Block(body) = body {
-        if let Some(label) = &body.label {
-            printer.label(label);
-        }
-        printer.word("{");
-        printer.neverbreak();
-        printer.cbox(INDENT);
-        printer.hardbreak_if_nonempty();
-        printer.inner_attrs(&body.attrs);
-        for stmt in body.block.stmts.iter().delimited() {
-            printer.stmt(&stmt, stmt.is_last);
-        }
-        printer.offset(-INDENT);
-        printer.end();
-        printer.word("}");
-    } else {
-        printer.neverbreak();
-        printer.cbox(INDENT);
-        let okay_to_brace = expr::parseable_as_stmt(body);
-        printer.scan_break(BreakToken {
-            pre_break: Some(if okay_to_brace { '{' } else { '(' }),
-            ..BreakToken::default()
-        });
-        printer.expr_beginning_of_line(body, false, true, FixupContext::new_match_arm());
-        printer.scan_break(BreakToken {
-            offset: -INDENT,
-            pre_break: (okay_to_brace && stmt::add_semi(body)).then_some(';'),
-            post_break: if okay_to_brace { "}" } else { ")," },
-            no_break: requires_comma_to_be_match_arm(body).unwrap().then_some(','),
-            ..BreakToken::default()
-        });
-        printer.end();
+    if let Some(precondition) = &arm_syntax.precondition {
+        printer.word(", if ");
+        printer.expr(precondition, FixupContext::NONE);
     }
+    printer.word(" => ");
 
+    arm::arm_body(printer, &arm_syntax.body);
     printer.end();
 }
 
-fn requires_comma_to_be_match_arm(expr: &Expr) -> syn::Result<bool> {
-    match expr {
-        Expr::If(_)
-        | Expr::Match(_)
-        | Expr::Block(_) | Expr::Unsafe(_) // both under ExprKind::Block in rustc
-        | Expr::While(_)
-        | Expr::Loop(_)
-        | Expr::ForLoop(_)
-        | Expr::TryBlock(_)
-        | Expr::Const(_) => Ok(false),
-
-        Expr::Array(_)
-        | Expr::Assign(_)
-        | Expr::Async(_)
-        | Expr::Await(_)
-        | Expr::Binary(_)
-        | Expr::Break(_)
-        | Expr::Call(_)
-        | Expr::Cast(_)
-        | Expr::Closure(_)
-        | Expr::Continue(_)
-        | Expr::Field(_)
-        | Expr::Group(_)
-        | Expr::Index(_)
-        | Expr::Infer(_)
-        | Expr::Let(_)
-        | Expr::Lit(_)
-        | Expr::Macro(_)
-        | Expr::MethodCall(_)
-        | Expr::Paren(_)
-        | Expr::Path(_)
-        | Expr::Range(_)
-        | Expr::RawAddr(_)
-        | Expr::Reference(_)
-        | Expr::Repeat(_)
-        | Expr::Return(_)
-        | Expr::Struct(_)
-        | Expr::Try(_)
-        | Expr::Tuple(_)
-        | Expr::Unary(_)
-        | Expr::Yield(_)
-        | Expr::Verbatim(_) => Ok(true),
-        _ => Err(syn::Error::new(expr.span(), "unhandled expression")),
-    }
-}
-
 #[cfg(test)]
 mod test {
     use crate::macro_syntax::MacroFactory;
@@ -373,4 +336,170 @@ fn func() {
 
         assert_eq_text!(formatted, expected_formatted);
     }
+
+    #[test]
+    fn test_replace_select_biased() {
+        let code = r#"
+fn func() {
+    select! {
+        biased;   val1 = future1.expr() => { a.expr() }
+        val2 = future2.expr() => b.expr(),
+    }
+}
+        "#;
+
+        let formatted = format_file(code);
+
+        let expected_formatted = r#"
+fn func() {
+    select! {
+        biased;
+        val1 = future1.expr() => a.expr(),
+        val2 = future2.expr() => b.expr(),
+    }
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+
+    #[test]
+    fn test_replace_select_precondition() {
+        let code = r#"
+fn func() {
+    select! {
+        val1 = future1.expr(), if   a.is_some()   => { a.expr() }
+        Some(val2) = future2.expr(),if !done => b.expr(),
+    }
+}
+        "#;
+
+        let formatted = format_file(code);
+
+        let expected_formatted = r#"
+fn func() {
+    select! {
+        val1 = future1.expr(), if a.is_some() => a.expr(),
+        Some(val2) = future2.expr(), if !done => b.expr(),
+    }
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+
+    #[test]
+    fn test_replace_select_else() {
+        let code = r#"
+fn func() {
+    select! {
+        val1 = future1.expr() => a.expr(),
+        else   =>   { break }
+    }
+}
+        "#;
+
+        let formatted = format_file(code);
+
+        let expected_formatted = r#"
+fn func() {
+    select! {
+        val1 = future1.expr() => a.expr(),
+        else => break,
+    }
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+
+    #[test]
+    fn test_replace_select_mixed() {
+        let code = r#"
+fn func() {
+    loop {
+        select! {
+            biased;
+            Some(val1) = future1.expr(), if aaaaaaaaaaaaaaaaaaaaaaaa.is_some() => { stmt(); a.expr() }
+            val2 = future2.expr() => b.expr().expr().expr().expr().expr().expr().expr().expr().expr(),
+            else => {
+                stmt();
+                break;
+            }
+        }
+    }
+}
+        "#;
+
+        let formatted = format_file(code);
+
+        let expected_formatted = r#"
+fn func() {
+    loop {
+        select! {
+            biased;
+            Some(val1) = future1.expr(), if aaaaaaaaaaaaaaaaaaaaaaaa.is_some() => {
+                stmt();
+                a.expr()
+            }
+            val2 = future2.expr() => {
+                b.expr().expr().expr().expr().expr().expr().expr().expr().expr()
+            }
+            else => {
+                stmt();
+                break;
+            }
+        }
+    }
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+
+    #[test]
+    fn test_replace_select_after_code() {
+        let code = r#"
+fn func() {
+    let value = select! {
+        val1 = future1.expr() => { a.expr() }
+    };
+    handle(a,
+        select! {
+            val1 = future1.expr() => aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa.expr(bbbbbbbbbbbbbbbbbbbbbbbbbbbbb),
+        });
+}
+        "#;
+
+        let formatted = format_file(code);
+
+        let expected_formatted = r#"
+fn func() {
+    let value = select! {
+        val1 = future1.expr() => a.expr(),
+    };
+    handle(a,
+        select! {
+            val1 = future1.expr() => {
+                aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa.expr(bbbbbbbbbbbbbbbbbbbbbbbbbbbbb)
+            }
+        });
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+
+    #[test]
+    fn test_parse_select_else_not_last() {
+        let code = r#"
+select! {
+    else => break,
+    val1 = future1.expr() => a.expr(),
+}
+        "#;
+
+        let mac: Macro = syn::parse_str(code).unwrap();
+        assert!(SelectFactory::parse(&mac).is_none());
+    }
 }
@@ -0,0 +1,536 @@
+use crate::config::Config;
+use crate::layout;
+use crate::macro_syntax::{MacroFormatter, MacroSyntax};
+use crate::macros::arm::{self, ArmComma, requires_comma_to_be_match_arm};
+use prettyplease::INDENT;
+use prettyplease::algorithm::Printer;
+use prettyplease::fixup::FixupContext;
+use prettyplease::iter::IterDelimited;
+use serde::Deserialize;
+use syn::parse::{ParseStream, Parser};
+use syn::punctuated::Punctuated;
+use syn::token::Comma;
+use syn::{Arm, Block, Expr, FieldValue, Item, Macro, MacroDelimiter, Member, Stmt};
+
+/// Generic syntax of a macro, configured per macro name in `plsfmt.toml`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MacroShape {
+    /// Comma separated expressions, like the arguments of a function call
+    Args,
+    /// Statements, like the body of a function
+    Block,
+    /// Items, like the content of a module
+    Items,
+    /// Comma separated `field: value` pairs, like a struct literal
+    Fields,
+    /// `pattern => expr` arms, like a match expression
+    MatchArms,
+}
+
+/// Commas printed between and after the elements of the comma separated shapes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Separators {
+    /// As rustfmt prints the corresponding syntax: a trailing comma if the elements are broken,
+    /// and no comma after match arms with a block body
+    Rustfmt,
+    /// A comma between all elements, and a trailing comma if the elements are broken, for
+    /// matchers like `$($e:expr),* $(,)?`
+    Optional,
+    /// A comma only between elements, for matchers like `$($e:expr),*`
+    Between,
+    /// A comma after each element, for matchers like `$($e:expr,)*`
+    Terminated,
+}
+
+/// Formats invocations of a macro according to its [`MacroShape`]
+pub struct ShapeFormatter {
+    name: String,
+    shape: MacroShape,
+    separators: Separators,
+}
+
+impl ShapeFormatter {
+    /// Formatter printing the commas of the comma separated shapes according to `separators`
+    pub fn new(name: impl Into<String>, shape: MacroShape, separators: Separators) -> Self {
+        Self {
+            name: name.into(),
+            shape,
+            separators,
+        }
+    }
+}
+
+impl MacroFormatter for ShapeFormatter {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn parse(&self, mac: &Macro) -> Option<Box<dyn MacroSyntax>> {
+        parse_shape(mac, self.shape, self.separators)
+    }
+}
+
+/// Parses the tokens of an invocation with the given shape
+pub(crate) fn parse_shape(
+    mac: &Macro,
+    shape: MacroShape,
+    separators: Separators,
+) -> Option<Box<dyn MacroSyntax>> {
+    let tokens = mac.tokens.clone();
+    let content = match shape {
+        MacroShape::Args => Content::Args(Punctuated::parse_terminated.parse2(tokens).ok()?),
+        MacroShape::Block => Content::Block(Block::parse_within.parse2(tokens).ok()?),
+        MacroShape::Items => Content::Items(parse_items.parse2(tokens).ok()?),
+        MacroShape::Fields => {
+            let fields: Punctuated<FieldValue, Comma> =
+                Punctuated::parse_terminated.parse2(tokens).ok()?;
+            if fields.iter().any(|field| !field.attrs.is_empty()) {
+                return None;
+            }
+            Content::Fields(fields)
+        }
+        MacroShape::MatchArms => Content::MatchArms(parse_arms.parse2(tokens).ok()?),
+    };
+
+    Some(Box::new(ShapeSyntax {
+        delimiter: mac.delimiter.clone(),
+        column: layout::delimiter_column(mac),
+        content,
+        separators,
+    }))
+}
+
+struct ShapeSyntax {
+    delimiter: MacroDelimiter,
+    /// Column of the opening delimiter
+    column: isize,
+    content: Content,
+    separators: Separators,
+}
+
+enum Content {
+    Args(Punctuated<Expr, Comma>),
+    Block(Vec<Stmt>),
+    Items(Vec<Item>),
+    Fields(Punctuated<FieldValue, Comma>),
+    MatchArms(Vec<Arm>),
+}
+
+impl MacroSyntax for ShapeSyntax {
+    fn delimiter_replacement(&self, base_indent: isize, config: &Config) -> String {
+        layout::print_at(config, base_indent, self.column, |printer, base_indent| {
+            shape(printer, self, base_indent)
+        })
+    }
+
+    fn match_arms(&self) -> bool {
+        matches!(self.content, Content::MatchArms(_))
+    }
+
+    fn optional_commas(&self) -> bool {
+        // The trailing comma is only printed if the elements are broken
+        matches!(self.separators, Separators::Rustfmt | Separators::Optional)
+    }
+}
+
+fn parse_items(input: ParseStream) -> syn::Result<Vec<Item>> {
+    let mut items = Vec::new();
+    while !input.is_empty() {
+        items.push(input.parse()?);
+    }
+    Ok(items)
+}
+
+fn parse_arms(input: ParseStream) -> syn::Result<Vec<Arm>> {
+    let mut arms = Vec::new();
+    while !input.is_empty() {
+        let arm: Arm = input.parse()?;
+        if !arm.attrs.is_empty() {
+            return Err(input.error("attributes on arms are not supported"));
+        }
+        requires_comma_to_be_match_arm(&arm.body)?;
+        arms.push(arm);
+    }
+    Ok(arms)
+}
+
+fn shape(printer: &mut Printer, shape_syntax: &ShapeSyntax, base_indent: isize) {
+    let (open, close, brace) = match shape_syntax.delimiter {
+        MacroDelimiter::Paren(_) => ("(", ")", false),
+        MacroDelimiter::Brace(_) => ("{", "}", true),
+        MacroDelimiter::Bracket(_) => ("[", "]", false),
+    };
+
+    let separators = shape_syntax.separators;
+    printer.word(open);
+    match &shape_syntax.content {
+        Content::Args(args) => list(
+            printer,
+            args,
+            brace,
+            separators,
+            base_indent,
+            |printer, arg| {
+                printer.expr(arg, FixupContext::NONE);
+            },
+        ),
+        Content::Block(stmts) => lines(printer, stmts, base_indent, |printer, stmt, is_last| {
+            printer.stmt(stmt, is_last);
+        }),
+        Content::Items(items) => lines(printer, items, base_indent, |printer, item, _| {
+            printer.item(item);
+        }),
+        Content::Fields(fields) => list(printer, fields, brace, separators, base_indent, field),
+        Content::MatchArms(arms) => lines(
+            printer,
+            arms,
+            base_indent,
+            |printer, arm_syntax, is_last| {
+                let comma = match separators {
+                    Separators::Rustfmt => ArmComma::Required,
+                    Separators::Between if is_last => ArmComma::Never,
+                    Separators::Optional | Separators::Between | Separators::Terminated => {
+                        ArmComma::Always
+                    }
+                };
+                arm(printer, arm_syntax, comma);
+                printer.hardbreak();
+            },
+        ),
+    }
+    printer.word(close);
+}
+
+/// Comma separated elements that are kept on one line if they fit
+fn list<T>(
+    printer: &mut Printer,
+    elements: &Punctuated<T, Comma>,
+    brace: bool,
+    separators: Separators,
+    base_indent: isize,
+    print: impl Fn(&mut Printer, &T),
+) {
+    printer.cbox(INDENT + base_indent);
+    if brace {
+        printer.space_if_nonempty();
+    } else {
+        printer.zerobreak();
+    }
+    for element in elements.iter().delimited() {
+        print(printer, &element);
+        match separators {
+            Separators::Rustfmt | Separators::Optional if brace => {
+                printer.trailing_comma_or_space(element.is_last);
+            }
+            Separators::Rustfmt | Separators::Optional => printer.trailing_comma(element.is_last),
+            Separators::Between | Separators::Terminated => {
+                if !element.is_last || separators == Separators::Terminated {
+                    printer.word(",");
+                }
+                if !element.is_last || brace {
+                    printer.space();
+                } else {
+                    printer.zerobreak();
+                }
+            }
+        }
+    }
+    printer.offset(-INDENT);
+    printer.end();
+}
+
+/// Elements that each end with a line break
+pub(crate) fn lines<T>(
+    printer: &mut Printer,
+    elements: &[T],
+    base_indent: isize,
+    print: impl Fn(&mut Printer, &T, bool),
+) {
+    printer.neverbreak();
+    printer.cbox(INDENT + base_indent);
+    printer.hardbreak_if_nonempty();
+    for element in elements.iter().delimited() {
+        print(printer, &element, element.is_last);
+    }
+    printer.offset(-INDENT);
+    printer.end();
+}
+
+fn field(printer: &mut Printer, field_value: &FieldValue) {
+    match &field_value.member {
+        Member::Named(ident) => printer.word(ident.to_string()),
+        Member::Unnamed(index) => printer.word(index.index.to_string()),
+    }
+    if field_value.colon_token.is_some() {
+        printer.word(": ");
+        printer.ibox(0);
+        printer.expr(&field_value.expr, FixupContext::NONE);
+        printer.end();
+    }
+}
+
+fn arm(printer: &mut Printer, arm_syntax: &Arm, comma: ArmComma) {
+    printer.ibox(0);
+    printer.pat(&arm_syntax.pat);
+    if let Some((_, guard)) = &arm_syntax.guard {
+        printer.word(" if ");
+        printer.expr(guard, FixupContext::NONE);
+    }
+    printer.word(" => ");
+
+    arm::arm_body_with_comma(printer, &arm_syntax.body, comma);
+    printer.end();
+}
+
+#[cfg(test)]
+mod test {
+    use crate::macros::shape::MacroShape;
+    use crate::{ConfigFile, Formatter, assert_eq_text};
+
+    fn format_with_shape(name: &str, shape: MacroShape, code: &str) -> String {
+        let mut config_file = ConfigFile::default();
+        config_file.macros.insert(name.to_string(), shape);
+        let formatter = Formatter::builder().config_file(&config_file).build();
+        formatter.format_str(code).unwrap()
+    }
+
+    #[test]
+    fn test_args() {
+        let code = r#"
+fn func() {
+    my_args!(a.expr(),b  +  c);
+    my_args!(aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa, bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb, cccccccccccccccccccccc);
+}
+        "#;
+
+        let formatted = format_with_shape("my_args", MacroShape::Args, code);
+
+        let expected_formatted = r#"
+fn func() {
+    my_args!(a.expr(), b + c);
+    my_args!(
+        aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa,
+        bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb,
+        cccccccccccccccccccccc,
+    );
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+
+    #[test]
+    fn test_args_after_code() {
+        let code = r#"
+fn func() {
+    let list = my_args!(aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa, bbbbbbbbbbbbbbbbbbbbbbbbbbb, ccccccccccc);
+    let list = my_args!(aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa,bbbbbbbbbbbbbbbbbbbbbbbbbbb, c);
+    call(a,
+        my_args!(aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa, bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb, cccccccccccc));
+}
+        "#;
+
+        let formatted = format_with_shape("my_args", MacroShape::Args, code);
+
+        let expected_formatted = r#"
+fn func() {
+    let list = my_args!(
+        aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa,
+        bbbbbbbbbbbbbbbbbbbbbbbbbbb,
+        ccccccccccc,
+    );
+    let list = my_args!(aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa, bbbbbbbbbbbbbbbbbbbbbbbbbbb, c);
+    call(a,
+        my_args!(
+            aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa,
+            bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb,
+            cccccccccccc,
+        ));
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+
+    #[test]
+    fn test_block() {
+        let code = r#"
+fn func() {
+    my_block! { let a = 1; a.expr()
+    }
+}
+        "#;
+
+        let formatted = format_with_shape("my_block", MacroShape::Block, code);
+
+        let expected_formatted = r#"
+fn func() {
+    my_block! {
+        let a = 1;
+        a.expr()
+    }
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+
+    #[test]
+    fn test_items() {
+        let code = r#"
+define_events! {
+    struct A { a: u32 }
+    fn b() -> u32 { 1 }
+}
+        "#;
+
+        let formatted = format_with_shape("define_events", MacroShape::Items, code);
+
+        let expected_formatted = r#"
+define_events! {
+    struct A {
+        a: u32,
+    }
+    fn b() -> u32 {
+        1
+    }
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+
+    #[test]
+    fn test_fields() {
+        let code = r#"
+fn func() {
+    my_fields! {a:1,b  :  c.expr(), d};
+}
+        "#;
+
+        let formatted = format_with_shape("my_fields", MacroShape::Fields, code);
+
+        let expected_formatted = r#"
+fn func() {
+    my_fields! { a: 1, b: c.expr(), d };
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+
+    #[test]
+    fn test_match_arms() {
+        let code = r#"
+fn func() {
+    my_router! {
+        "/a" => { handle_a() }
+        path if path.starts_with("/b") => handle_b(path),
+        "/c" => handle_c(first_argument.expr(), second_argument.expr(), third_argument.expr()),
+        _ => {
+            let a = 1;
+            not_found(a)
+        }
+    }
+}
+        "#;
+
+        let formatted = format_with_shape("my_router", MacroShape::MatchArms, code);
+
+        let expected_formatted = r#"
+fn func() {
+    my_router! {
+        "/a" => handle_a(),
+        path if path.starts_with("/b") => handle_b(path),
+        "/c" => {
+            handle_c(
+                first_argument.expr(),
+                second_argument.expr(),
+                third_argument.expr(),
+            )
+        }
+        _ => {
+            let a = 1;
+            not_found(a)
+        }
+    }
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+
+    #[test]
+    fn test_not_matching_shape() {
+        let code = r#"
+fn func() {
+    my_args!(a => b);
+}
+        "#;
+
+        let formatted = format_with_shape("my_args", MacroShape::Args, code);
+
+        assert_eq_text!(formatted, code);
+    }
+
+    #[test]
+    fn test_fallback() {
+        let code = r#"
+macro_rules! local {
+    ($($e:expr),*) => {};
+}
+
+fn func() {
+    list!(1234 ,1234);
+    list!(1234 ,1234 ,);
+    list![if a {b} else {c}, if a {b} else {c}, if a {b} else {c}, if a {b} else {c}, if a {b} else {c}];
+    list![if a {b} else {c}, if a {b} else {c}, if a {b} else {c}, if a {b} else {c}, if a {b} else {c},];
+    list! { let a = 1; a.expr() }
+    list! { fn a() {} struct B; }
+    list!(fn a() {}, fn b() {});
+    vec![0 ;  4];
+}
+        "#;
+
+        let formatter = Formatter::builder().fallback(true).build();
+        let formatted = formatter.format_str(code).unwrap();
+
+        let expected_formatted = r#"
+macro_rules! local {
+    ($($e:expr),*) => {};
+}
+
+fn func() {
+    list!(1234, 1234);
+    list!(1234, 1234,);
+    list![
+        if a { b } else { c },
+        if a { b } else { c },
+        if a { b } else { c },
+        if a { b } else { c },
+        if a { b } else { c }
+    ];
+    list![
+        if a { b } else { c },
+        if a { b } else { c },
+        if a { b } else { c },
+        if a { b } else { c },
+        if a { b } else { c },
+    ];
+    list! {
+        let a = 1;
+        a.expr()
+    }
+    list! {
+        fn a() {}
+        struct B;
+    }
+    list!(fn a() {}, fn b() {});
+    vec![0 ;  4];
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+}
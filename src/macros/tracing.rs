@@ -0,0 +1,397 @@
+use crate::config::Config;
+use crate::layout;
+use crate::macro_syntax::{MacroFormatter, MacroSyntax};
+use crate::macros::format::{self, FormatArg};
+use prettyplease::algorithm::Printer;
+use prettyplease::fixup::FixupContext;
+use syn::ext::IdentExt;
+use syn::parse::{Parse, ParseStream, Parser};
+use syn::{Expr, Ident, LitStr, Macro, MacroDelimiter, Token};
+
+/// Paths of the tracing event macros, and whether the level is the first argument
+pub(crate) const TRACING_EVENTS: [(&str, bool); 6] = [
+    ("tracing::trace", false),
+    ("tracing::debug", false),
+    ("tracing::info", false),
+    ("tracing::warn", false),
+    ("tracing::error", false),
+    ("tracing::event", true),
+];
+
+/// Paths of the tracing span macros, and whether the level is the first argument
+pub(crate) const TRACING_SPANS: [(&str, bool); 6] = [
+    ("tracing::trace_span", false),
+    ("tracing::debug_span", false),
+    ("tracing::info_span", false),
+    ("tracing::warn_span", false),
+    ("tracing::error_span", false),
+    ("tracing::span", true),
+];
+
+/// Formats invocations of a tracing event or span macro, like `tracing::info!` or
+/// `tracing::info_span!`. The arguments are the `target:`, `parent:` and `name:` prefixes, the
+/// level for `event!` and `span!`, the span name, the fields, and the message format string
+/// and its arguments. They are kept on one line if they fit, and otherwise broken one per line.
+pub struct TracingFormatter {
+    path: &'static str,
+    level: bool,
+    span: bool,
+}
+
+impl TracingFormatter {
+    /// Formatter of the event macro at `path`, with the level as the first argument after the
+    /// prefixes if `level`
+    pub fn event(path: &'static str, level: bool) -> Self {
+        Self {
+            path,
+            level,
+            span: false,
+        }
+    }
+
+    /// Formatter of the span macro at `path`, with the level before the span name if `level`
+    pub fn span(path: &'static str, level: bool) -> Self {
+        Self {
+            path,
+            level,
+            span: true,
+        }
+    }
+}
+
+impl MacroFormatter for TracingFormatter {
+    fn name(&self) -> &str {
+        self.path.rsplit("::").next().unwrap_or(self.path)
+    }
+
+    fn path(&self) -> Option<&str> {
+        Some(self.path)
+    }
+
+    fn parse(&self, mac: &Macro) -> Option<Box<dyn MacroSyntax>> {
+        let tracing_syntax =
+            (|input: ParseStream| TracingSyntax::parse(input, mac, self.level, self.span))
+                .parse2(mac.tokens.clone())
+                .ok()?;
+        Some(Box::new(tracing_syntax))
+    }
+}
+
+struct TracingSyntax {
+    delimiter: MacroDelimiter,
+    /// Column of the opening delimiter
+    column: isize,
+    args: Vec<TracingArg>,
+    trailing_comma: bool,
+}
+
+enum TracingArg {
+    /// `target: expr`, `parent: expr` or `name: expr`
+    Prefix {
+        key: Ident,
+        value: Expr,
+    },
+    Level(Expr),
+    /// Name of a span
+    Name(Expr),
+    Field(Field),
+    /// Format string of the message
+    Message(LitStr),
+    /// Argument of the message format string
+    FormatArg(FormatArg),
+}
+
+/// `name = value`, `name = %value`, `name = ?value` or the shorthand `name`, `%name` and `?name`,
+/// where the name is a dotted path like `a.b` or a string literal
+pub(crate) struct Field {
+    name: FieldName,
+    sigil: Option<Sigil>,
+    value: Option<Expr>,
+}
+
+enum FieldName {
+    Path(Vec<Ident>),
+    Literal(LitStr),
+}
+
+#[derive(Clone, Copy)]
+enum Sigil {
+    /// `%`, recorded with `Display`
+    Display,
+    /// `?`, recorded with `Debug`
+    Debug,
+}
+
+impl TracingSyntax {
+    fn parse(input: ParseStream, mac: &Macro, level: bool, span: bool) -> syn::Result<Self> {
+        let mut args = Vec::new();
+        let mut trailing_comma = false;
+        while !input.is_empty() {
+            let prefixes_only = args
+                .iter()
+                .all(|arg| matches!(arg, TracingArg::Prefix { .. }));
+            let has_name = args.iter().any(|arg| matches!(arg, TracingArg::Name(_)));
+            let has_message = args.iter().any(|arg| matches!(arg, TracingArg::Message(_)));
+            let arg = if prefixes_only && is_prefix(input) {
+                let key = input.parse()?;
+                input.parse::<Token![:]>()?;
+                TracingArg::Prefix {
+                    key,
+                    value: input.parse()?,
+                }
+            } else if level && !args.iter().any(|arg| matches!(arg, TracingArg::Level(_))) {
+                TracingArg::Level(input.parse()?)
+            } else if span && !has_name {
+                TracingArg::Name(input.parse()?)
+            } else if has_message {
+                let arg = input.parse()?;
+                if let (
+                    Some(TracingArg::FormatArg(FormatArg::Named { .. })),
+                    FormatArg::Positional(_),
+                ) = (args.last(), &arg)
+                {
+                    return Err(input.error("positional argument after named arguments"));
+                }
+                TracingArg::FormatArg(arg)
+            } else if !span && input.peek(LitStr) && !input.peek2(Token![=]) {
+                TracingArg::Message(input.parse()?)
+            } else {
+                TracingArg::Field(input.parse()?)
+            };
+            args.push(arg);
+
+            if input.is_empty() {
+                break;
+            }
+            input.parse::<Token![,]>()?;
+            trailing_comma = input.is_empty();
+        }
+        if level && !args.iter().any(|arg| matches!(arg, TracingArg::Level(_))) {
+            return Err(input.error("missing level"));
+        }
+        if span && !args.iter().any(|arg| matches!(arg, TracingArg::Name(_))) {
+            return Err(input.error("missing span name"));
+        }
+
+        Ok(Self {
+            delimiter: mac.delimiter.clone(),
+            column: layout::delimiter_column(mac),
+            args,
+            trailing_comma,
+        })
+    }
+}
+
+fn is_prefix(input: ParseStream) -> bool {
+    let fork = input.fork();
+    fork.parse::<Ident>().is_ok_and(|key| {
+        (key == "target" || key == "parent" || key == "name")
+            && fork.peek(Token![:])
+            && !fork.peek(Token![::])
+    })
+}
+
+impl Parse for Field {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let shorthand_sigil = parse_sigil(input)?;
+        let name = if shorthand_sigil.is_none() && input.peek(LitStr) {
+            let name = input.parse()?;
+            if !input.peek(Token![=]) {
+                return Err(input.error("expected `=` after string field name"));
+            }
+            FieldName::Literal(name)
+        } else {
+            let mut path = vec![Ident::parse_any(input)?];
+            while input.peek(Token![.]) {
+                input.parse::<Token![.]>()?;
+                path.push(Ident::parse_any(input)?);
+            }
+            FieldName::Path(path)
+        };
+
+        if shorthand_sigil.is_some() || !input.peek(Token![=]) || input.peek(Token![==]) {
+            return Ok(Field {
+                name,
+                sigil: shorthand_sigil,
+                value: None,
+            });
+        }
+        input.parse::<Token![=]>()?;
+        Ok(Field {
+            name,
+            sigil: parse_sigil(input)?,
+            value: Some(input.parse()?),
+        })
+    }
+}
+
+fn parse_sigil(input: ParseStream) -> syn::Result<Option<Sigil>> {
+    if input.peek(Token![%]) {
+        input.parse::<Token![%]>()?;
+        Ok(Some(Sigil::Display))
+    } else if input.peek(Token![?]) {
+        input.parse::<Token![?]>()?;
+        Ok(Some(Sigil::Debug))
+    } else {
+        Ok(None)
+    }
+}
+
+impl MacroSyntax for TracingSyntax {
+    fn delimiter_replacement(&self, base_indent: isize, config: &Config) -> String {
+        layout::print_at(config, base_indent, self.column, |printer, base_indent| {
+            format::list(
+                printer,
+                &self.delimiter,
+                &self.args,
+                self.trailing_comma,
+                base_indent,
+                tracing_arg,
+            )
+        })
+    }
+
+    fn optional_commas(&self) -> bool {
+        true
+    }
+}
+
+fn tracing_arg(printer: &mut Printer, arg: &TracingArg) {
+    match arg {
+        TracingArg::Prefix { key, value } => {
+            printer.word(format!("{key}: "));
+            printer.expr(value, FixupContext::NONE);
+        }
+        TracingArg::Level(expr) | TracingArg::Name(expr) => printer.expr(expr, FixupContext::NONE),
+        TracingArg::Field(field) => self::field(printer, field),
+        TracingArg::Message(message) => printer.word(message.token().to_string()),
+        TracingArg::FormatArg(arg) => format::format_arg(printer, arg),
+    }
+}
+
+pub(crate) fn field(printer: &mut Printer, field: &Field) {
+    if field.value.is_none() {
+        sigil(printer, field.sigil);
+    }
+    match &field.name {
+        FieldName::Path(path) => {
+            let path: Vec<String> = path.iter().map(Ident::to_string).collect();
+            printer.word(path.join("."));
+        }
+        FieldName::Literal(literal) => printer.word(literal.token().to_string()),
+    }
+    if let Some(value) = &field.value {
+        printer.word(" = ");
+        sigil(printer, field.sigil);
+        printer.expr(value, FixupContext::NONE);
+    }
+}
+
+fn sigil(printer: &mut Printer, sigil: Option<Sigil>) {
+    match sigil {
+        Some(Sigil::Display) => printer.word("%"),
+        Some(Sigil::Debug) => printer.word("?"),
+        None => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{assert_eq_text, format_file};
+
+    #[test]
+    fn test_replace_tracing_events() {
+        let code = r#"
+use tracing::{event, info, trace, Level};
+
+fn func() {
+    info!(a.b=1,?c,%d,"message {}",x);
+    trace!(test = %"test", test = %"test", test = %"test", test = %"test", test = %"test", "{} {}", "test", "test");
+    event!(target: "app", parent: &span, Level::INFO, "field.name" = ?value, message = "text");
+    tracing::warn!(name: "event", target: "app", aaaaaaaaaaaaaaaaaaaaaa = ?bbbbbbbbbbbbbbbb, ccccccccccccccc, "message");
+    tracing::error!(error = %err,);
+}
+        "#;
+
+        let formatted = format_file(code);
+
+        let expected_formatted = r#"
+use tracing::{event, info, trace, Level};
+
+fn func() {
+    info!(a.b = 1, ?c, %d, "message {}", x);
+    trace!(
+        test = %"test",
+        test = %"test",
+        test = %"test",
+        test = %"test",
+        test = %"test",
+        "{} {}",
+        "test",
+        "test"
+    );
+    event!(
+        target: "app",
+        parent: &span,
+        Level::INFO,
+        "field.name" = ?value,
+        message = "text"
+    );
+    tracing::warn!(
+        name: "event",
+        target: "app",
+        aaaaaaaaaaaaaaaaaaaaaa = ?bbbbbbbbbbbbbbbb,
+        ccccccccccccccc,
+        "message"
+    );
+    tracing::error!(error = %err);
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+
+    #[test]
+    fn test_replace_tracing_events_unparseable() {
+        let code = r#"
+fn func() {
+    tracing::info!("message {a} {}",  a = 1,  b);
+    tracing::event!(target:  "app");
+    tracing::info!(a = 1,  target: "app", "message");
+}
+        "#;
+
+        let formatted = format_file(code);
+
+        assert_eq_text!(formatted, code);
+    }
+
+    #[test]
+    fn test_replace_tracing_spans() {
+        let code = r#"
+fn func() {
+    let span = tracing::info_span!("request",method=%method,path = ?path);
+    let span = tracing::span!(target: "app", Level::INFO, "request", aaaaaaaaaaaaaaaaaaaa = %bbbbbbbbbbbbbbbbb, cccccccccccccc);
+    let span = tracing::span!( Level::INFO );
+}
+        "#;
+
+        let formatted = format_file(code);
+
+        let expected_formatted = r#"
+fn func() {
+    let span = tracing::info_span!("request", method = %method, path = ?path);
+    let span = tracing::span!(
+        target: "app",
+        Level::INFO,
+        "request",
+        aaaaaaaaaaaaaaaaaaaa = %bbbbbbbbbbbbbbbbb,
+        cccccccccccccc
+    );
+    let span = tracing::span!( Level::INFO );
+}
+        "#;
+
+        assert_eq_text!(formatted, expected_formatted);
+    }
+}
@@ -1,10 +1,34 @@
 use crate::macro_syntax::MacroSyntax;
+use serde::Serialize;
+use std::borrow::Cow;
 
 pub struct ParsedMacro<'ast> {
     pub macro_syntax: Box<dyn MacroSyntax>,
-    pub syn_macro: &'ast syn::Macro,
+    /// Borrowed from the file, or owned for the arguments of an attribute
+    pub syn_macro: Cow<'ast, syn::Macro>,
 }
 
+/// Macro invocation bound to a macro factory that is not formatted
+pub struct SkippedMacro<'ast> {
+    pub reason: SkipReason,
+    pub syn_macro: Cow<'ast, syn::Macro>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SkipReason {
+    /// The tokens do not parse with the grammar of the macro factory
+    Unparseable,
+    /// The formatter declined to format the invocation
+    Declined,
+    /// The replacement does not have the same tokens as the invocation
+    TokensChanged,
+    /// The formatter failed with the message, e.g. an external plugin could not be started
+    Failed(String),
+}
+
+/// Line (1-based) and column (0-based, in characters)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub struct Position {
     pub line: usize,
     pub column: usize,
@@ -0,0 +1,218 @@
+use crate::config::NewlineStyle;
+use crate::diff;
+use crate::replace::Replacement;
+use proc_macro2::{Delimiter, TokenStream, TokenTree};
+use std::ops::Range;
+use std::str::FromStr;
+
+/// Maps byte offsets in the original content to byte offsets in the formatted content, e.g. to
+/// keep the cursor on the same token when formatting in an editor. Offsets outside the
+/// formatted macros are mapped exactly. Inside the macros, offsets are anchored to the token
+/// they are in, or the next token if they are between tokens or in a token that was removed.
+#[derive(Debug, Clone, Default)]
+pub struct PositionMap {
+    macros: Vec<MacroPositions>,
+    /// Offsets in the content before conversion of line endings where a `\r` was inserted
+    /// (`1`) or removed (`-1`)
+    newline_changes: Vec<(usize, isize)>,
+}
+
+#[derive(Debug, Clone)]
+struct MacroPositions {
+    original: Range<usize>,
+    formatted: Range<usize>,
+    /// Byte ranges of matching tokens, relative to the start of the original and the
+    /// formatted macro
+    anchors: Vec<(Range<usize>, Range<usize>)>,
+}
+
+impl PositionMap {
+    pub(crate) fn new(
+        content: &str,
+        replacements: &[Replacement],
+        replaced: &str,
+        newline_style: NewlineStyle,
+    ) -> Self {
+        let mut macros = Vec::new();
+        let mut delta = 0isize;
+        for replacement in replacements {
+            let original = &content[replacement.range.clone()];
+            let original_tokens = tokens(original);
+            let formatted_tokens = tokens(&replacement.text);
+            let anchors = diff::longest_common_subsequence(
+                &original_tokens
+                    .iter()
+                    .map(|(text, _)| text)
+                    .collect::<Vec<_>>(),
+                &formatted_tokens
+                    .iter()
+                    .map(|(text, _)| text)
+                    .collect::<Vec<_>>(),
+            )
+            .into_iter()
+            .map(|(i, j)| (original_tokens[i].1.clone(), formatted_tokens[j].1.clone()))
+            .collect();
+
+            let start = (replacement.range.start as isize + delta) as usize;
+            macros.push(MacroPositions {
+                original: replacement.range.clone(),
+                formatted: start..start + replacement.text.len(),
+                anchors,
+            });
+            delta += replacement.text.len() as isize - replacement.range.len() as isize;
+        }
+
+        Self {
+            macros,
+            newline_changes: newline_changes(replaced, newline_style.is_crlf(content)),
+        }
+    }
+
+    /// Maps an offset in the original content to the formatted content
+    pub fn map(&self, offset: usize) -> usize {
+        let offset = self.map_replaced(offset);
+        let delta: isize = self
+            .newline_changes
+            .iter()
+            .take_while(|(position, _)| *position < offset)
+            .map(|(_, delta)| delta)
+            .sum();
+        (offset as isize + delta) as usize
+    }
+
+    /// Maps to the content before conversion of line endings
+    fn map_replaced(&self, offset: usize) -> usize {
+        let mut delta = 0isize;
+        for mac in &self.macros {
+            if offset < mac.original.start {
+                break;
+            }
+            if offset < mac.original.end {
+                let relative = offset - mac.original.start;
+                return mac
+                    .anchors
+                    .iter()
+                    .find(|(original, _)| relative < original.end)
+                    .map_or(mac.formatted.end, |(original, formatted)| {
+                        let in_token = relative.saturating_sub(original.start);
+                        mac.formatted.start + formatted.start + in_token.min(formatted.len())
+                    });
+            }
+            delta = mac.formatted.end as isize - mac.original.end as isize;
+        }
+        (offset as isize + delta) as usize
+    }
+}
+
+/// Text and byte range of each token, with group delimiters as separate tokens
+pub(crate) fn tokens(text: &str) -> Vec<(String, Range<usize>)> {
+    fn collect(stream: TokenStream, tokens: &mut Vec<(String, Range<usize>)>) {
+        for token in stream {
+            match token {
+                TokenTree::Group(group) => {
+                    let (open, close) = match group.delimiter() {
+                        Delimiter::Parenthesis => ("(", ")"),
+                        Delimiter::Brace => ("{", "}"),
+                        Delimiter::Bracket => ("[", "]"),
+                        Delimiter::None => ("", ""),
+                    };
+                    if !open.is_empty() {
+                        tokens.push((open.to_string(), group.span_open().byte_range()));
+                    }
+                    collect(group.stream(), tokens);
+                    if !close.is_empty() {
+                        tokens.push((close.to_string(), group.span_close().byte_range()));
+                    }
+                }
+                token => tokens.push((token.to_string(), token.span().byte_range())),
+            }
+        }
+    }
+
+    let mut tokens = Vec::new();
+    if let Ok(stream) = TokenStream::from_str(text) {
+        collect(stream, &mut tokens);
+    }
+    tokens
+}
+
+fn newline_changes(text: &str, crlf: bool) -> Vec<(usize, isize)> {
+    let bytes = text.as_bytes();
+    bytes
+        .iter()
+        .enumerate()
+        .filter_map(|(index, &byte)| {
+            let after_cr = index > 0 && bytes[index - 1] == b'\r';
+            match (byte, crlf) {
+                (b'\n', true) if !after_cr => Some((index, 1)),
+                (b'\n', false) if after_cr => Some((index - 1, -1)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Config, Formatter, NewlineStyle};
+
+    #[test]
+    fn test_position_map() {
+        let code = r#"
+fn func() {
+    select! {
+        val1 = future1.expr() => {
+            a.expr()
+        }
+    }
+    after();
+}
+"#;
+
+        let (formatted, position_map) = Formatter::default()
+            .format_str_with_position_map(code)
+            .unwrap();
+
+        // before macro
+        let offset = code.find("func").unwrap();
+        assert_eq!(position_map.map(offset), offset);
+
+        // inside token in macro
+        let offset = code.find("expr() =>").unwrap() + 2;
+        assert_eq!(&code[offset..offset + 4], "pr()");
+        let mapped = position_map.map(offset);
+        assert_eq!(&formatted[mapped..mapped + 4], "pr()");
+
+        // removed brace anchored at next token
+        let offset = code.find("{\n            a").unwrap();
+        let mapped = position_map.map(offset);
+        assert_eq!(&formatted[mapped..mapped + 8], "a.expr()");
+
+        // whitespace anchored at next token
+        let offset = code.find("a.expr()").unwrap() - 3;
+        let mapped = position_map.map(offset);
+        assert_eq!(&formatted[mapped..mapped + 8], "a.expr()");
+
+        // after macro
+        let offset = code.find("after").unwrap();
+        let mapped = position_map.map(offset);
+        assert_eq!(&formatted[mapped..mapped + 5], "after");
+    }
+
+    #[test]
+    fn test_position_map_newline_conversion() {
+        let code = "fn func() {\r\n    select! {\r\n        val1 = future1.expr() => { a.expr() }\r\n    }\r\n    after();\r\n}\r\n";
+
+        let formatter = Formatter::builder()
+            .config(Config {
+                newline_style: NewlineStyle::Unix,
+                ..Config::default()
+            })
+            .build();
+        let (formatted, position_map) = formatter.format_str_with_position_map(code).unwrap();
+
+        let offset = code.find("after").unwrap();
+        let mapped = position_map.map(offset);
+        assert_eq!(&formatted[mapped..mapped + 5], "after");
+    }
+}
@@ -1,35 +1,150 @@
-use crate::model::ParsedMacro;
+use crate::config::Config;
+use crate::layout;
+use crate::macro_syntax::MacroSyntax;
+use crate::macros::arm;
+use crate::model::{ParsedMacro, SkipReason, SkippedMacro};
+use crate::position_map;
 
+use proc_macro2::{Group, TokenStream, TokenTree};
+use std::borrow::Cow;
+use std::ops::Range;
+use std::str::FromStr;
 use syn::spanned::Spanned;
 
-pub fn replace(content: &str, mut parsed_macros: Vec<ParsedMacro>) -> String {
+/// Formatted replacement of a macro invocation
+pub struct Replacement<'ast> {
+    pub syn_macro: Cow<'ast, syn::Macro>,
+    /// Byte range of the macro delimiters and the tokens between them in the original content
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+impl Replacement<'_> {
+    pub fn is_changed(&self, content: &str) -> bool {
+        content[self.range.clone()] != self.text
+    }
+}
+
+/// Formats the parsed macros, ordered by position in `content`. Invocations that the formatter
+/// declines, or whose tokens the replacement changes, are returned as skipped.
+pub fn replacements<'ast>(
+    content: &str,
+    mut parsed_macros: Vec<ParsedMacro<'ast>>,
+    config: &Config,
+) -> (Vec<Replacement<'ast>>, Vec<SkippedMacro<'ast>>) {
     parsed_macros.sort_by_key(|parsed_macro| parsed_macro.syn_macro.span().start());
 
-    let mut out = String::new();
-    let mut cursor = 0;
+    let mut replacements = Vec::new();
+    let mut skipped_macros = Vec::new();
     for parsed_macro in parsed_macros {
-        out.push_str(
-            &content[cursor
-                ..parsed_macro
-                    .syn_macro
-                    .delimiter
-                    .span()
-                    .span()
-                    .byte_range()
-                    .start],
-        );
-        out.push_str(
-            &parsed_macro
-                .macro_syntax
-                .delimiter_replacement(parsed_macro.syn_macro.path.span().start().column as isize),
+        let syn_macro = parsed_macro.syn_macro;
+        let range = syn_macro.delimiter.span().span().byte_range();
+        let macro_syntax = &parsed_macro.macro_syntax;
+        let indentation = line_indentation(content, syn_macro.path.span().byte_range().start);
+        // Tabs count as one column in the base indentation and the delimiter column, so the
+        // columns they take beyond that are taken off the max width
+        let config = &Config {
+            max_width: config
+                .max_width
+                .saturating_sub(tab_columns(indentation, config.tab_spaces)),
+            ..config.clone()
+        };
+        let base_indent = indentation.chars().count();
+        match macro_syntax.try_delimiter_replacement(base_indent as isize, config) {
+            Ok(text) if same_tokens(&content[range.clone()], &text, macro_syntax.as_ref()) => {
+                let text = if indentation.contains('\t') {
+                    layout::restore_indentation(&text, indentation)
+                } else {
+                    text
+                };
+                replacements.push(Replacement {
+                    syn_macro,
+                    range,
+                    text: config.newline_style.apply(content, text),
+                });
+            }
+            result => skipped_macros.push(SkippedMacro {
+                reason: result.err().unwrap_or(SkipReason::TokensChanged),
+                syn_macro,
+            }),
+        }
+    }
+
+    (replacements, skipped_macros)
+}
+
+/// Indentation of the line containing the byte `offset`
+fn line_indentation(content: &str, offset: usize) -> &str {
+    let line_start = content[..offset].rfind('\n').map_or(0, |index| index + 1);
+    let line = &content[line_start..offset];
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+/// Columns the tabs in `indentation` take beyond one column each, with tab stops every
+/// `tab_spaces` columns
+fn tab_columns(indentation: &str, tab_spaces: usize) -> usize {
+    let width = indentation.chars().fold(0, |width, char| match char {
+        '\t' => (width / tab_spaces.max(1) + 1) * tab_spaces.max(1),
+        _ => width + 1,
+    });
+    width - indentation.chars().count()
+}
+
+/// Whether the delimited groups `original` and `replacement` have the same delimiters and the
+/// same tokens between them. Commas after a closing brace or before a closing delimiter are ignored if the
+/// macro has [`MacroSyntax::optional_commas`], and the braces around arm bodies and the commas
+/// after them if it has [`MacroSyntax::match_arms`].
+fn same_tokens(original: &str, replacement: &str, macro_syntax: &dyn MacroSyntax) -> bool {
+    let optional_commas = macro_syntax.optional_commas();
+    let compared_tokens = |text: &str| -> Option<Vec<String>> {
+        let text = if macro_syntax.match_arms() {
+            let Some(TokenTree::Group(group)) =
+                TokenStream::from_str(text).ok()?.into_iter().next()
+            else {
+                return None;
+            };
+            let arms = arm::normalized_arms(group.stream())?;
+            Cow::Owned(Group::new(group.delimiter(), arms).to_string())
+        } else {
+            Cow::Borrowed(text)
+        };
+        let tokens: Vec<String> = position_map::tokens(&text)
+            .into_iter()
+            .map(|(token, _)| token)
+            .collect();
+        let (open, close) = (tokens.first()?, tokens.last()?);
+        let inner = tokens.get(1..tokens.len() - 1)?;
+        let mut compared = vec![open.clone()];
+        compared.extend(
+            inner
+                .iter()
+                .enumerate()
+                .filter(|&(index, token)| {
+                    let after_brace = index > 0 && inner[index - 1] == "}";
+                    let before_close = inner
+                        .get(index + 1)
+                        .is_none_or(|next| matches!(next.as_str(), ")" | "]" | "}"));
+                    token != "," || !optional_commas || !(after_brace || before_close)
+                })
+                .map(|(_, token)| token.clone()),
         );
-        cursor = parsed_macro
-            .syn_macro
-            .delimiter
-            .span()
-            .span()
-            .byte_range()
-            .end;
+        compared.push(close.clone());
+        Some(compared)
+    };
+
+    match (compared_tokens(original), compared_tokens(replacement)) {
+        (Some(original), Some(replacement)) => original == replacement,
+        _ => false,
+    }
+}
+
+pub fn replace(content: &str, replacements: &[Replacement]) -> String {
+    let mut out = String::new();
+    let mut cursor = 0;
+    for replacement in replacements {
+        out.push_str(&content[cursor..replacement.range.start]);
+        out.push_str(&replacement.text);
+        cursor = replacement.range.end;
     }
     out.push_str(std::str::from_utf8(&content.as_bytes()[cursor..]).unwrap());
 
@@ -73,3 +188,41 @@ pub fn replace(content: &str, mut parsed_macros: Vec<ParsedMacro>) -> String {
 //         Some(self.cmp(other))
 //     }
 // }
+
+#[cfg(test)]
+mod test {
+    use crate::config::Config;
+    use crate::macro_syntax::MacroSyntax;
+    use crate::replace::same_tokens;
+
+    struct Syntax {
+        optional_commas: bool,
+    }
+
+    impl MacroSyntax for Syntax {
+        fn delimiter_replacement(&self, _base_indent: isize, _config: &Config) -> String {
+            unimplemented!()
+        }
+
+        fn optional_commas(&self) -> bool {
+            self.optional_commas
+        }
+    }
+
+    #[test]
+    fn test_same_tokens() {
+        let exact = &Syntax {
+            optional_commas: false,
+        };
+        let optional_commas = &Syntax {
+            optional_commas: true,
+        };
+
+        assert!(same_tokens("(a ,b)", "(\n    a,\n    b\n)", exact));
+        assert!(!same_tokens("(a, b)", "(a, b,)", exact));
+        assert!(same_tokens("(a, b)", "(a, b,)", optional_commas));
+        assert!(!same_tokens("(a, b)", "(a, c)", optional_commas));
+        assert!(!same_tokens("(a, b)", "{ a, b }", optional_commas));
+        assert!(!same_tokens("[a, b]", "(a, b)", optional_commas));
+    }
+}
@@ -0,0 +1,141 @@
+use crate::model::{Position, SkipReason, SkippedMacro};
+use crate::replace::Replacement;
+use serde::Serialize;
+use std::ops::Range;
+use syn::spanned::Spanned;
+
+/// Describes the formatting of each macro invocation bound to a macro factory
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FormatReport {
+    /// Ordered by position in the file
+    pub macros: Vec<MacroReport>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MacroReport {
+    /// Path of the macro as written in the invocation
+    pub path: String,
+    /// Byte range of the macro delimiters and the tokens between them, which is what the
+    /// replacement replaces
+    pub range: Range<usize>,
+    pub start: Position,
+    pub end: Position,
+    /// Formatted delimiters and tokens, `None` if skipped
+    pub replacement: Option<String>,
+    pub changed: bool,
+    pub skipped: Option<SkipReason>,
+}
+
+impl FormatReport {
+    pub(crate) fn new(
+        content: &str,
+        replacements: &[Replacement],
+        skipped_macros: &[SkippedMacro],
+    ) -> Self {
+        let mut macros: Vec<MacroReport> = replacements
+            .iter()
+            .map(|replacement| MacroReport {
+                replacement: Some(replacement.text.clone()),
+                changed: replacement.is_changed(content),
+                ..MacroReport::unformatted(&replacement.syn_macro)
+            })
+            .chain(skipped_macros.iter().map(|skipped| MacroReport {
+                skipped: Some(skipped.reason.clone()),
+                ..MacroReport::unformatted(&skipped.syn_macro)
+            }))
+            .collect();
+        macros.sort_by_key(|mac| mac.range.start);
+
+        Self { macros }
+    }
+
+    pub fn changed(&self) -> bool {
+        self.macros.iter().any(|mac| mac.changed)
+    }
+}
+
+impl MacroReport {
+    fn unformatted(syn_macro: &syn::Macro) -> Self {
+        let span = syn_macro.delimiter.span().span();
+        Self {
+            path: path_to_string(&syn_macro.path),
+            range: span.byte_range(),
+            start: Position::new(span.start().line, span.start().column),
+            end: Position::new(span.end().line, span.end().column),
+            replacement: None,
+            changed: false,
+            skipped: None,
+        }
+    }
+}
+
+pub(crate) fn path_to_string(path: &syn::Path) -> String {
+    let mut string = String::new();
+    if path.leading_colon.is_some() {
+        string.push_str("::");
+    }
+    for (index, segment) in path.segments.iter().enumerate() {
+        if index > 0 {
+            string.push_str("::");
+        }
+        string.push_str(&segment.ident.to_string());
+    }
+    string
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Formatter;
+    use crate::model::{Position, SkipReason};
+
+    #[test]
+    fn test_report() {
+        let code = r#"
+fn func() {
+    tokio::select! {
+        val1 = future1.expr() => { a.expr() }
+    }
+    select! {
+        val1 = future1.expr() => a.expr(),
+    }
+    select! { val1 = }
+    println!("test");
+}
+"#;
+
+        let (_, report) = Formatter::default().format_str_with_report(code).unwrap();
+
+        assert_eq!(report.macros.len(), 4);
+
+        let mac = &report.macros[0];
+        assert_eq!(mac.path, "tokio::select");
+        assert_eq!(
+            &code[mac.range.clone()],
+            "{\n        val1 = future1.expr() => { a.expr() }\n    }"
+        );
+        assert_eq!(mac.start, Position::new(3, 19));
+        assert_eq!(mac.end, Position::new(5, 5));
+        assert_eq!(
+            mac.replacement.as_deref(),
+            Some("{\n        val1 = future1.expr() => a.expr(),\n    }")
+        );
+        assert!(mac.changed);
+        assert_eq!(mac.skipped, None);
+
+        let mac = &report.macros[1];
+        assert_eq!(mac.path, "select");
+        assert!(!mac.changed);
+        assert_eq!(mac.skipped, None);
+
+        let mac = &report.macros[2];
+        assert_eq!(mac.path, "select");
+        assert_eq!(mac.replacement, None);
+        assert!(!mac.changed);
+        assert_eq!(mac.skipped, Some(SkipReason::Unparseable));
+
+        let mac = &report.macros[3];
+        assert_eq!(mac.path, "println");
+        assert!(!mac.changed);
+        assert_eq!(mac.skipped, None);
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::{BTreeMap, BTreeSet};
+use syn::visit::{self, Visit};
+use syn::{ItemMacro, ItemUse, UseTree};
+
+/// Resolves paths of macro invocations using the `use` items and `macro_rules!` definitions in
+/// a file. Scopes are not taken into account, a name imported with different paths in the
+/// same file is left unresolved, and `crate::` and `self::` prefixes are ignored.
+#[derive(Debug, Default)]
+pub(crate) struct Resolver {
+    /// Imported names and their full paths, `None` if imported with different paths
+    imports: BTreeMap<String, Option<Vec<String>>>,
+    local_macros: BTreeSet<String>,
+}
+
+/// Resolution of the path of a macro invocation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Resolution {
+    /// Macro defined with `macro_rules!` in the file
+    Local(String),
+    /// Full path of the macro, e.g. `tokio::select`
+    Path(String),
+    /// Name of a macro that could not be resolved, e.g. imported with a glob import
+    Unresolved(String),
+}
+
+impl Resolution {
+    /// Name of the macro, which is the last segment of the path
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            Resolution::Local(name) | Resolution::Unresolved(name) => name,
+            Resolution::Path(path) => path.rsplit("::").next().unwrap_or(path),
+        }
+    }
+}
+
+impl Resolver {
+    pub(crate) fn new(file: &syn::File) -> Self {
+        let mut resolver = Self::default();
+        resolver.visit_file(file);
+        resolver
+    }
+
+    pub(crate) fn resolve(&self, path: &syn::Path) -> Resolution {
+        let mut segments: Vec<String> = path
+            .segments
+            .iter()
+            .map(|segment| segment.ident.to_string())
+            .collect();
+        if segments.len() > 1 && (segments[0] == "crate" || segments[0] == "self") {
+            segments.remove(0);
+        }
+
+        if let Some(Some(import)) = self.imports.get(&segments[0]) {
+            segments.splice(..1, import.iter().cloned());
+        }
+
+        match segments.as_slice() {
+            [name] if self.local_macros.contains(name) => Resolution::Local(name.clone()),
+            [name] => Resolution::Unresolved(name.clone()),
+            _ => Resolution::Path(segments.join("::")),
+        }
+    }
+
+    fn add_import(&mut self, name: String, path: Vec<String>) {
+        self.imports
+            .entry(name)
+            .and_modify(|existing| {
+                if existing.as_ref() != Some(&path) {
+                    *existing = None;
+                }
+            })
+            .or_insert(Some(path));
+    }
+
+    fn add_use_tree(&mut self, prefix: &mut Vec<String>, tree: &UseTree) {
+        match tree {
+            UseTree::Path(use_path) => {
+                prefix.push(use_path.ident.to_string());
+                self.add_use_tree(prefix, &use_path.tree);
+                prefix.pop();
+            }
+            UseTree::Name(use_name) => {
+                self.add_use(prefix, &use_name.ident, &use_name.ident);
+            }
+            UseTree::Rename(use_rename) => {
+                self.add_use(prefix, &use_rename.ident, &use_rename.rename);
+            }
+            UseTree::Group(use_group) => {
+                for tree in &use_group.items {
+                    self.add_use_tree(prefix, tree);
+                }
+            }
+            UseTree::Glob(_) => {}
+        }
+    }
+
+    fn add_use(&mut self, prefix: &[String], ident: &syn::Ident, rename: &syn::Ident) {
+        let mut path = prefix.to_vec();
+        if ident != "self" {
+            path.push(ident.to_string());
+        }
+        let name = if rename == "self" {
+            path.last().cloned()
+        } else {
+            Some(rename.to_string())
+        };
+        if let Some(name) = name {
+            self.add_import(name, path);
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for Resolver {
+    fn visit_item_use(&mut self, item: &'ast ItemUse) {
+        self.add_use_tree(&mut Vec::new(), &item.tree);
+    }
+
+    fn visit_item_macro(&mut self, item: &'ast ItemMacro) {
+        if let (Some(ident), true) = (&item.ident, item.mac.path.is_ident("macro_rules")) {
+            self.local_macros.insert(ident.to_string());
+        }
+        visit::visit_item_macro(self, item);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::resolve::{Resolution, Resolver};
+
+    #[test]
+    fn test_resolve() {
+        let code = r#"
+use tokio::select as tselect;
+use futures::{self as fut, join};
+use glob::*;
+
+macro_rules! local {
+    () => {};
+}
+        "#;
+        let resolver = Resolver::new(&syn::parse_file(code).unwrap());
+        let resolve = |path: &str| resolver.resolve(&syn::parse_str(path).unwrap());
+
+        assert_eq!(
+            resolve("tselect"),
+            Resolution::Path("tokio::select".to_string())
+        );
+        assert_eq!(
+            resolve("fut::select"),
+            Resolution::Path("futures::select".to_string())
+        );
+        assert_eq!(
+            resolve("join"),
+            Resolution::Path("futures::join".to_string())
+        );
+        assert_eq!(
+            resolve("tokio::select"),
+            Resolution::Path("tokio::select".to_string())
+        );
+        assert_eq!(resolve("local"), Resolution::Local("local".to_string()));
+        assert_eq!(
+            resolve("select"),
+            Resolution::Unresolved("select".to_string())
+        );
+        assert_eq!(
+            resolve("crate::tselect"),
+            Resolution::Path("tokio::select".to_string())
+        );
+        assert_eq!(
+            resolve("self::join"),
+            Resolution::Path("futures::join".to_string())
+        );
+        assert_eq!(
+            resolve("crate::local"),
+            Resolution::Local("local".to_string())
+        );
+    }
+}
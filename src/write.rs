@@ -0,0 +1,29 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+
+/// Replaces the content of `path` by writing to a temporary file in the same directory and
+/// renaming it over the original. The original file is hence never left truncated, and its
+/// permissions are kept.
+pub fn write_atomic(path: &Path, content: &str) -> io::Result<()> {
+    let path = path.canonicalize()?;
+    let file_name = path.file_name().unwrap().to_string_lossy();
+    let tmp_path = path.with_file_name(format!(".{}.plsfmt-{}.tmp", file_name, process::id()));
+
+    let result = (|| {
+        let mut tmp_file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp_path)?;
+        tmp_file.write_all(content.as_bytes())?;
+        tmp_file.set_permissions(fs::metadata(&path)?.permissions())?;
+        tmp_file.sync_all()?;
+        fs::rename(&tmp_path, &path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}